@@ -1,78 +1,82 @@
-//! This example demonstrates how to use the `odra-cli` tool to deploy and interact with a smart contract.
+//! Deploys the `StakingPool` and upgrades it in place with `odra-cli`.
 
-use casperflow_protocol::flipper::Flipper;
-use odra::host::{HostEnv, NoArgs};
-use odra::schema::casper_contract_schema::NamedCLType;
+use casperflow_protocol::staking_pool::{StakingPool, StakingPoolInitArgs, STORAGE_VERSION};
+use odra::host::{HostEnv, InstallConfig, NoArgs};
 use odra_cli::{
     deploy::DeployScript,
     scenario::{Args, Error, Scenario, ScenarioMetadata},
     CommandArg, ContractProvider, DeployedContractsContainer, DeployerExt,
-    OdraCli, 
+    OdraCli,
 };
 
-/// Deploys the `Flipper` and adds it to the container.
-pub struct FlipperDeployScript;
+const UNBONDING_DELAY: u64 = 14 * 60 * 60 * 1000; // 14 hours
 
-impl DeployScript for FlipperDeployScript {
+/// Deploys the `StakingPool` as an upgradable contract and adds it to the container.
+pub struct StakingPoolDeployScript;
+
+impl DeployScript for StakingPoolDeployScript {
     fn deploy(
         &self,
         env: &HostEnv,
         container: &mut DeployedContractsContainer
     ) -> Result<(), odra_cli::deploy::Error> {
-        let _flipper = Flipper::load_or_deploy(
-            &env,
-            NoArgs,
+        let _pool = StakingPool::load_or_deploy_with_cfg(
+            env,
+            StakingPoolInitArgs {
+                unbonding_delay: UNBONDING_DELAY,
+            },
+            InstallConfig::upgradable::<StakingPool>(),
             container,
-            350_000_000_000 // Adjust gas limit as needed
+            500_000_000_000 // Adjust gas limit as needed
         )?;
 
         Ok(())
     }
 }
 
-/// Scenario that flips the state of the deployed `Flipper` contract a specified number of times.
-pub struct FlippingScenario;
+/// Installs the current `StakingPool` code over the deployed contract, then migrates
+/// its storage if it was written by an older version.
+pub struct UpgradeScenario;
 
-impl Scenario for FlippingScenario {
+impl Scenario for UpgradeScenario {
     fn args(&self) -> Vec<CommandArg> {
-        vec![CommandArg::new(
-            "number",
-            "The number of times to flip the state",
-            NamedCLType::U64,
-        )]
+        vec![]
     }
 
     fn run(
         &self,
         env: &HostEnv,
         container: &DeployedContractsContainer,
-        args: Args
+        _args: Args
     ) -> Result<(), Error> {
-        let mut contract = container.contract_ref::<Flipper>(env)?;
-        let n = args.get_single::<u64>("name")?;
+        let deployed = container.contract_ref::<StakingPool>(env)?;
+
+        env.set_gas(500_000_000_000);
+        let mut pool = StakingPool::try_upgrade(env, *deployed.address(), NoArgs)?;
 
-        env.set_gas(50_000_000);
-        for _ in 0..n {
-            contract.try_flip()?;
+        let from_version = pool.get_storage_version();
+        if from_version < STORAGE_VERSION {
+            env.set_gas(50_000_000_000);
+            pool.try_migrate(from_version)?;
         }
 
         Ok(())
     }
 }
 
-impl ScenarioMetadata for FlippingScenario {
-    const NAME: &'static str = "flip";
+impl ScenarioMetadata for UpgradeScenario {
+    const NAME: &'static str = "upgrade";
     const DESCRIPTION: &'static str =
-        "Flips the state of the deployed flipper contract a specified number of times";
+        "Upgrades the deployed staking pool and migrates its storage to the current version";
 }
 
 /// Main function to run the CLI tool.
 pub fn main() {
     OdraCli::new()
         .about("CLI tool for casperflow_protocol smart contract")
-        .deploy(FlipperDeployScript)
-        .contract::<Flipper>()
-        .scenario(FlippingScenario)
+        .deploy(StakingPoolDeployScript)
+        .contract::<StakingPool>()
+        .scenario(UpgradeScenario)
         .build()
         .run();
 }
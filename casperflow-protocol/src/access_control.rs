@@ -0,0 +1,80 @@
+use odra::prelude::*;
+
+#[odra::odra_type]
+pub enum Role {
+    DefaultAdmin,
+    Operator,
+    Reporter,
+    Pauser,
+    Treasury,
+}
+
+#[odra::event]
+pub struct RoleGranted {
+    pub role: Role,
+    pub account: Address,
+    pub sender: Address,
+}
+
+#[odra::event]
+pub struct RoleRevoked {
+    pub role: Role,
+    pub account: Address,
+    pub sender: Address,
+}
+
+/// Role membership. `DefaultAdmin` administers every role, including itself.
+#[odra::module(events = [RoleGranted, RoleRevoked])]
+pub struct AccessControl {
+    roles: Mapping<(Role, Address), bool>,
+}
+
+#[odra::module]
+impl AccessControl {
+    pub fn has_role(&self, role: Role, account: Address) -> bool {
+        self.roles.get(&(role, account)).unwrap_or_default()
+    }
+
+    pub(crate) fn check_role(&self, role: Role, account: Address) {
+        if !self.has_role(role, account) {
+            self.env().revert(Error::MissingRole);
+        }
+    }
+
+    pub(crate) fn grant_role(&mut self, role: Role, account: Address, sender: Address) {
+        self.check_role(Role::DefaultAdmin, sender);
+        self.set_role(role, account, sender, true);
+    }
+
+    pub(crate) fn revoke_role(&mut self, role: Role, account: Address, sender: Address) {
+        self.check_role(Role::DefaultAdmin, sender);
+        self.set_role(role, account, sender, false);
+    }
+
+    pub(crate) fn renounce_role(&mut self, role: Role, account: Address) {
+        self.set_role(role, account, account, false);
+    }
+
+    // Grants without checking the sender, for initial setup
+    pub(crate) fn setup_role(&mut self, role: Role, account: Address) {
+        self.set_role(role, account, account, true);
+    }
+
+    fn set_role(&mut self, role: Role, account: Address, sender: Address, granted: bool) {
+        if self.has_role(role.clone(), account) == granted {
+            return;
+        }
+        self.roles.set(&(role.clone(), account), granted);
+
+        if granted {
+            self.env().emit_event(RoleGranted { role, account, sender });
+        } else {
+            self.env().emit_event(RoleRevoked { role, account, sender });
+        }
+    }
+}
+
+#[odra::odra_error]
+pub enum Error {
+    MissingRole = 600,
+}
@@ -0,0 +1,89 @@
+use odra::prelude::*;
+
+const MAX_UTILIZATION_BP: u32 = 10_000;
+const MAX_INSTANT_FEE_BP: u32 = 1_000; // 10% hard ceiling
+
+/// Piecewise-linear instant unstake fee: rises from `base_fee_bp` at 0% utilization
+/// to `kink_fee_bp` at `kink_utilization_bp`, then steeply to `max_fee_bp` at 100%.
+#[odra::odra_type]
+pub struct FeeCurve {
+    pub base_fee_bp: u32,
+    pub kink_utilization_bp: u32,
+    pub kink_fee_bp: u32,
+    pub max_fee_bp: u32,
+}
+
+impl FeeCurve {
+    /// Flat 0.5% until the pool is 80% utilized, then up to 5%.
+    pub const DEFAULT: FeeCurve = FeeCurve {
+        base_fee_bp: 50,
+        kink_utilization_bp: 8_000,
+        kink_fee_bp: 50,
+        max_fee_bp: 500,
+    };
+
+    pub fn is_valid(&self) -> bool {
+        self.kink_utilization_bp > 0
+            && self.kink_utilization_bp <= MAX_UTILIZATION_BP
+            && self.base_fee_bp <= self.kink_fee_bp
+            && self.kink_fee_bp <= self.max_fee_bp
+            && self.max_fee_bp <= MAX_INSTANT_FEE_BP
+    }
+
+    pub fn fee_bp(&self, utilization_bp: u32) -> u32 {
+        let utilization = utilization_bp.min(MAX_UTILIZATION_BP) as u64;
+        let kink = self.kink_utilization_bp as u64;
+        let (base, kink_fee, max) = (self.base_fee_bp as u64, self.kink_fee_bp as u64, self.max_fee_bp as u64);
+
+        let fee = if utilization <= kink {
+            base + (kink_fee - base) * utilization / kink
+        } else {
+            kink_fee + (max - kink_fee) * (utilization - kink) / (MAX_UTILIZATION_BP as u64 - kink)
+        };
+        fee as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FeeCurve;
+
+    #[test]
+    fn default_curve_is_flat_until_kink() {
+        let curve = FeeCurve::DEFAULT;
+        assert!(curve.is_valid());
+        assert_eq!(curve.fee_bp(0), 50);
+        assert_eq!(curve.fee_bp(8_000), 50);
+        assert_eq!(curve.fee_bp(9_000), 275);
+        assert_eq!(curve.fee_bp(10_000), 500);
+        assert_eq!(curve.fee_bp(20_000), 500);
+    }
+
+    #[test]
+    fn fee_interpolates_on_both_segments() {
+        let curve = FeeCurve {
+            base_fee_bp: 10,
+            kink_utilization_bp: 5_000,
+            kink_fee_bp: 110,
+            max_fee_bp: 610,
+        };
+        assert_eq!(curve.fee_bp(2_500), 60);
+        assert_eq!(curve.fee_bp(5_000), 110);
+        assert_eq!(curve.fee_bp(7_500), 360);
+    }
+
+    #[test]
+    fn invalid_curves_are_rejected() {
+        let mut curve = FeeCurve::DEFAULT;
+        curve.kink_utilization_bp = 0;
+        assert!(!curve.is_valid());
+
+        let mut curve = FeeCurve::DEFAULT;
+        curve.kink_fee_bp = 600;
+        assert!(!curve.is_valid());
+
+        let mut curve = FeeCurve::DEFAULT;
+        curve.max_fee_bp = 1_001;
+        assert!(!curve.is_valid());
+    }
+}
@@ -2,5 +2,14 @@
 #![cfg_attr(not(test), no_main)]
 extern crate alloc;
 
+pub mod access_control;
 pub mod token;
+pub mod withdrawal_queue;
+pub mod validator_registry;
+pub mod routing;
+pub mod reward_oracle;
+pub mod reentrancy_guard;
+pub mod liquidity;
+pub mod fee_curve;
+pub mod math;
 pub mod staking_pool;
@@ -0,0 +1,62 @@
+use odra::prelude::*;
+use odra::casper_types::U512;
+
+/// Share ledger for instant-unstake liquidity providers. Shares are a claim on a
+/// proportional part of the instant pool's value, which grows as fees accrue.
+#[odra::module]
+pub struct LiquidityPool {
+    shares: Mapping<Address, U512>,
+    total_shares: Var<U512>,
+}
+
+#[odra::module]
+impl LiquidityPool {
+    pub fn shares_of(&self, provider: Address) -> U512 {
+        self.shares.get(&provider).unwrap_or_default()
+    }
+
+    pub fn total_shares(&self) -> U512 {
+        self.total_shares.get_or_default()
+    }
+
+    /// Issues shares for `amount` of CSPR added to a pool worth `pool_value` before the deposit.
+    pub(crate) fn mint_shares(&mut self, provider: Address, amount: U512, pool_value: U512) -> U512 {
+        let total_shares = self.total_shares();
+        let shares = if total_shares.is_zero() || pool_value.is_zero() {
+            amount
+        } else {
+            amount * total_shares / pool_value
+        };
+        if shares.is_zero() {
+            self.env().revert(Error::ZeroShares);
+        }
+
+        self.shares.set(&provider, self.shares_of(provider) + shares);
+        self.total_shares.set(total_shares + shares);
+        shares
+    }
+
+    /// Redeems `shares` and returns their CSPR value in a pool worth `pool_value`.
+    pub(crate) fn burn_shares(&mut self, provider: Address, shares: U512, pool_value: U512) -> U512 {
+        let balance = self.shares_of(provider);
+        if shares.is_zero() {
+            self.env().revert(Error::ZeroShares);
+        }
+        if balance < shares {
+            self.env().revert(Error::InsufficientShares);
+        }
+
+        let total_shares = self.total_shares();
+        let amount = shares * pool_value / total_shares;
+
+        self.shares.set(&provider, balance - shares);
+        self.total_shares.set(total_shares - shares);
+        amount
+    }
+}
+
+#[odra::odra_error]
+pub enum Error {
+    InsufficientShares = 500,
+    ZeroShares = 501,
+}
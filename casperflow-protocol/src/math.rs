@@ -0,0 +1,160 @@
+use odra::casper_types::U512;
+
+/// Scale of exchange rates: `RATE_PRECISION` is a 1:1 rate.
+pub const RATE_PRECISION: u64 = 1_000_000_000_000_000_000; // 1e18
+
+/// Virtual CSPR and stCSPR added to both sides of every conversion. The rate starts
+/// at 1:1, and a jump in assets behind a tiny supply accrues mostly to the virtual
+/// shares, so inflating the rate costs an attacker far more than later depositors
+/// can lose to rounding.
+pub const VIRTUAL_OFFSET: u64 = 1_000_000_000; // 1 CSPR
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+/// `a * b / denominator` without forming the full product.
+///
+/// Exact for any denominator below 2^256; `None` on a zero denominator or when the
+/// result itself does not fit in a U512.
+pub fn mul_div(a: U512, b: U512, denominator: U512, rounding: Rounding) -> Option<U512> {
+    if denominator.is_zero() {
+        return None;
+    }
+
+    // With a = qa * d + ra and b = qb * d + rb:
+    // a * b / d = qa * qb * d + qa * rb + ra * qb + ra * rb / d
+    let (qa, ra) = a.div_mod(denominator);
+    let (qb, rb) = b.div_mod(denominator);
+    let (low, remainder) = ra.checked_mul(rb)?.div_mod(denominator);
+
+    let result = qa
+        .checked_mul(qb)?
+        .checked_mul(denominator)?
+        .checked_add(qa.checked_mul(rb)?)?
+        .checked_add(ra.checked_mul(qb)?)?
+        .checked_add(low)?;
+    if rounding == Rounding::Up && !remainder.is_zero() {
+        return result.checked_add(U512::one());
+    }
+    Some(result)
+}
+
+/// stCSPR worth `assets` CSPR.
+///
+/// Callers round in the pool's favor: down for shares minted, up for shares burned.
+pub fn to_shares(assets: U512, total_assets: U512, total_shares: U512, rounding: Rounding) -> Option<U512> {
+    mul_div(assets, with_offset(total_shares)?, with_offset(total_assets)?, rounding)
+}
+
+/// CSPR worth `shares` stCSPR; down for CSPR paid out, up for CSPR taken in.
+pub fn to_assets(shares: U512, total_assets: U512, total_shares: U512, rounding: Rounding) -> Option<U512> {
+    mul_div(shares, with_offset(total_assets)?, with_offset(total_shares)?, rounding)
+}
+
+/// CSPR per stCSPR, scaled by `RATE_PRECISION`.
+pub fn exchange_rate(total_assets: U512, total_shares: U512) -> Option<U512> {
+    mul_div(
+        with_offset(total_assets)?,
+        U512::from(RATE_PRECISION),
+        with_offset(total_shares)?,
+        Rounding::Down,
+    )
+}
+
+fn with_offset(total: U512) -> Option<U512> {
+    total.checked_add(U512::from(VIRTUAL_OFFSET))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exchange_rate, mul_div, to_assets, to_shares, Rounding, RATE_PRECISION, VIRTUAL_OFFSET};
+    use odra::casper_types::U512;
+
+    #[test]
+    fn mul_div_matches_native_arithmetic() {
+        for a in 0..40u64 {
+            for b in 0..40u64 {
+                for d in 1..40u64 {
+                    let (a512, b512, d512) = (U512::from(a), U512::from(b), U512::from(d));
+                    let down = a * b / d;
+                    let up = (a * b).div_ceil(d);
+                    assert_eq!(mul_div(a512, b512, d512, Rounding::Down), Some(U512::from(down)));
+                    assert_eq!(mul_div(a512, b512, d512, Rounding::Up), Some(U512::from(up)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mul_div_handles_products_beyond_u512() {
+        let max = U512::MAX;
+        assert_eq!(mul_div(max, max, max, Rounding::Down), Some(max));
+        assert_eq!(mul_div(max, U512::from(3), U512::from(3), Rounding::Down), Some(max));
+
+        let half = max / 2;
+        assert_eq!(mul_div(half, U512::from(4), U512::from(8), Rounding::Down), Some(half / 2));
+        let odd = max - 1;
+        assert_eq!(mul_div(odd, U512::from(2), U512::from(3), Rounding::Up), Some(odd / 3 * 2 + 2));
+    }
+
+    #[test]
+    fn mul_div_rejects_zero_denominator_and_overflowing_results() {
+        assert_eq!(mul_div(U512::one(), U512::one(), U512::zero(), Rounding::Down), None);
+        assert_eq!(mul_div(U512::MAX, U512::from(2), U512::one(), Rounding::Down), None);
+        assert_eq!(mul_div(U512::MAX, U512::one(), U512::one(), Rounding::Up), Some(U512::MAX));
+    }
+
+    #[test]
+    fn conversions_are_one_to_one_on_an_empty_pool() {
+        let amount = U512::from(1_234u64);
+        assert_eq!(to_shares(amount, U512::zero(), U512::zero(), Rounding::Up), Some(amount));
+        assert_eq!(to_assets(amount, U512::zero(), U512::zero(), Rounding::Down), Some(amount));
+        assert_eq!(exchange_rate(U512::zero(), U512::zero()), Some(U512::from(RATE_PRECISION)));
+    }
+
+    #[test]
+    fn conversions_round_in_the_pools_favor() {
+        for total_assets in 1..30u64 {
+            for total_shares in 1..30u64 {
+                for assets in 0..30u64 {
+                    let (ta, ts) = (U512::from(total_assets), U512::from(total_shares));
+                    let shares = to_shares(U512::from(assets), ta, ts, Rounding::Down).unwrap();
+                    let expected = assets * (total_shares + VIRTUAL_OFFSET) / (total_assets + VIRTUAL_OFFSET);
+                    assert_eq!(shares, U512::from(expected));
+
+                    // Redeeming against the pool after the deposit gives back no more than was put in
+                    let redeemed = to_assets(shares, ta + assets, ts + shares, Rounding::Down).unwrap();
+                    assert!(redeemed <= U512::from(assets));
+
+                    // Shares burned for an exact payout cover at least that payout
+                    let burned = to_shares(U512::from(assets), ta, ts, Rounding::Up).unwrap();
+                    assert!(to_assets(burned, ta, ts, Rounding::Down).unwrap() >= U512::from(assets));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exchange_rate_keeps_eighteen_decimals() {
+        let rate = exchange_rate(U512::from(10_000_400_000_000u64), U512::from(10_000_039_998_560u64));
+        assert_eq!(rate, Some(U512::from(1_000_035_996_400_379_544u64)));
+
+        let rate = exchange_rate(U512::from(2_000_000_000u64), U512::from(1_000_000_000u64));
+        assert_eq!(rate, Some(U512::from(1_500_000_000_000_000_000u64)));
+    }
+
+    #[test]
+    fn inflated_rate_behind_one_share_does_not_round_deposits_to_zero() {
+        // 50 CSPR of assets backing a single stCSPR
+        let (total_assets, total_shares) = (U512::from(50_000_000_001u64), U512::one());
+        let deposit = U512::from(40_000_000_000u64);
+        let shares = to_shares(deposit, total_assets, total_shares, Rounding::Down).unwrap();
+        assert_eq!(shares, U512::from(784_313_726u64));
+
+        let value = to_assets(shares, total_assets + deposit, total_shares + shares, Rounding::Down).unwrap();
+        assert_eq!(value, deposit - U512::from(8u64));
+    }
+}
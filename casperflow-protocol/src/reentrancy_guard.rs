@@ -0,0 +1,114 @@
+use odra::prelude::*;
+
+/// Rejects nested calls into guarded entrypoints, e.g. from a contract receiving
+/// CSPR in the middle of one. A revert rolls the lock back with the rest of the state.
+#[odra::module]
+pub struct ReentrancyGuard {
+    locked: Var<bool>,
+}
+
+#[odra::module]
+impl ReentrancyGuard {
+    pub(crate) fn enter(&mut self) {
+        if self.locked.get_or_default() {
+            self.env().revert(Error::ReentrantCall);
+        }
+        self.locked.set(true);
+    }
+
+    pub(crate) fn exit(&mut self) {
+        self.locked.set(false);
+    }
+}
+
+#[odra::odra_error]
+pub enum Error {
+    ReentrantCall = 700,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ReentrancyGuard};
+    use odra::host::{Deployer, HostRef, NoArgs};
+    use odra::prelude::*;
+
+    #[odra::external_contract]
+    pub trait PaymentReceiver {
+        fn on_payment(&mut self);
+    }
+
+    #[odra::external_contract]
+    pub trait GuardedPayer {
+        fn pay(&mut self, receiver: Address);
+    }
+
+    // Hands control to the receiver in the middle of a guarded call, like a payout would
+    #[odra::module]
+    pub struct Payer {
+        guard: SubModule<ReentrancyGuard>,
+        payments: Var<u32>,
+    }
+
+    #[odra::module]
+    impl Payer {
+        pub fn pay(&mut self, receiver: Address) {
+            self.guard.enter();
+            PaymentReceiverContractRef::new(self.env(), receiver).on_payment();
+            self.payments.set(self.payments() + 1);
+            self.guard.exit();
+        }
+
+        pub fn payments(&self) -> u32 {
+            self.payments.get_or_default()
+        }
+    }
+
+    #[odra::module]
+    pub struct HonestReceiver {
+        received: Var<u32>,
+    }
+
+    #[odra::module]
+    impl HonestReceiver {
+        pub fn on_payment(&mut self) {
+            self.received.set(self.received.get_or_default() + 1);
+        }
+    }
+
+    #[odra::module]
+    pub struct MaliciousReceiver {
+        payer: Var<Address>,
+    }
+
+    #[odra::module]
+    impl MaliciousReceiver {
+        pub fn init(&mut self, payer: Address) {
+            self.payer.set(payer);
+        }
+
+        pub fn on_payment(&mut self) {
+            let payer = self.payer.get().unwrap();
+            GuardedPayerContractRef::new(self.env(), payer).pay(self.env().self_address());
+        }
+    }
+
+    #[test]
+    fn reentry_from_a_receiver_is_rejected() {
+        let env = odra_test::env();
+        let mut payer = Payer::deploy(&env, NoArgs);
+        let honest = HonestReceiver::deploy(&env, NoArgs);
+        payer.pay(*honest.address());
+        assert_eq!(payer.payments(), 1);
+
+        // Paying the attacker reverts as a whole, including the outer payment
+        let attacker = MaliciousReceiver::deploy(&env, MaliciousReceiverInitArgs {
+            payer: *payer.address(),
+        });
+        assert_eq!(payer.try_pay(*attacker.address()), Err(Error::ReentrantCall.into()));
+        assert_eq!(payer.payments(), 1);
+
+        // The lock was rolled back with the revert
+        payer.pay(*honest.address());
+        assert_eq!(payer.payments(), 2);
+    }
+}
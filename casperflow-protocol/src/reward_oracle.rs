@@ -0,0 +1,116 @@
+use odra::prelude::*;
+use odra::casper_types::U512;
+
+pub const ERA_DURATION: u64 = 2 * 60 * 60 * 1000; // ~2 hours, in milliseconds
+const ERAS_PER_YEAR: u64 = 4_380;
+const DEFAULT_MAX_REWARD_APR_BP: u32 = 2_000; // 20% in basis points
+
+/// Off-chain reward reporting for environments where the pool cannot read its
+/// delegation balances. A report is accepted once `quorum` reporters agree on the
+/// same amount for an era; reporter membership is checked by the caller.
+///
+/// Era ids may only advance as fast as block time does from the start era, anchored
+/// at init or by the admin before the first report, so the APR bound cannot be
+/// stretched by reporting ahead and a far-future era cannot lock out later reports.
+#[odra::module]
+pub struct RewardOracle {
+    quorum: Var<u32>,
+    max_apr_bp: Var<u32>,
+    last_era: Var<u64>,
+    start_era: Var<u64>,
+    start_time: Var<u64>,  // block time at which `start_era` was anchored
+    votes: Mapping<(u64, Address), bool>,
+    tallies: Mapping<(u64, U512), u32>,
+}
+
+#[odra::module]
+impl RewardOracle {
+    pub fn init(&mut self) {
+        self.quorum.set(1);
+        self.max_apr_bp.set(DEFAULT_MAX_REWARD_APR_BP);
+        self.last_era.set(0);
+        self.start_era.set(0);
+        self.start_time.set(self.env().get_block_time());
+    }
+
+    pub fn quorum(&self) -> u32 {
+        self.quorum.get_or_default()
+    }
+
+    pub fn max_apr_bp(&self) -> u32 {
+        self.max_apr_bp.get_or_default()
+    }
+
+    pub fn last_era(&self) -> u64 {
+        self.last_era.get_or_default()
+    }
+
+    pub(crate) fn set_quorum(&mut self, quorum: u32) {
+        if quorum == 0 {
+            self.env().revert(Error::InvalidQuorum);
+        }
+        self.quorum.set(quorum);
+    }
+
+    pub(crate) fn set_max_apr_bp(&mut self, max_apr_bp: u32) {
+        self.max_apr_bp.set(max_apr_bp);
+    }
+
+    /// Anchors the era count at `era_id` as of now, so the first report can be for
+    /// the era after it. Only allowed before any report is accepted.
+    pub(crate) fn set_start_era(&mut self, era_id: u64) {
+        if self.last_era() != self.start_era.get_or_default() {
+            self.env().revert(Error::ReportingStarted);
+        }
+        self.start_era.set(era_id);
+        self.last_era.set(era_id);
+        self.start_time.set(self.env().get_block_time());
+    }
+
+    /// Records `reporter`'s vote and returns the rewards to credit once the era
+    /// reaches quorum.
+    pub(crate) fn submit(&mut self, reporter: Address, era_id: u64, amount: U512, total_delegated: U512) -> Option<U512> {
+        let last_era = self.last_era();
+        if era_id <= last_era {
+            self.env().revert(Error::StaleEra);
+        }
+        if self.votes.get(&(era_id, reporter)).unwrap_or_default() {
+            self.env().revert(Error::AlreadyReported);
+        }
+
+        // The era after the start era may be reported right away, each later one
+        // only once another era's worth of block time has passed
+        let eras_passed = (self.env().get_block_time() - self.start_time.get_or_default()) / ERA_DURATION;
+        if era_id - self.start_era.get_or_default() > eras_passed + 1 {
+            self.env().revert(Error::FutureEra);
+        }
+
+        // Bound the increase to the configured APR over the eras since the last report
+        let elapsed = era_id - last_era;
+        let max_rewards = total_delegated * U512::from(self.max_apr_bp()) * U512::from(elapsed)
+            / U512::from(10_000u64 * ERAS_PER_YEAR);
+        if amount > max_rewards {
+            self.env().revert(Error::RewardTooHigh);
+        }
+
+        self.votes.set(&(era_id, reporter), true);
+        let tally = self.tallies.get(&(era_id, amount)).unwrap_or_default() + 1;
+        self.tallies.set(&(era_id, amount), tally);
+
+        if tally < self.quorum() {
+            return None;
+        }
+        self.last_era.set(era_id);
+        Some(amount)
+    }
+}
+
+#[odra::odra_error]
+pub enum Error {
+    StaleEra = 401,
+    AlreadyReported = 402,
+    RewardTooHigh = 403,
+    InvalidQuorum = 404,
+    FutureEra = 405,
+    ReportingStarted = 406,
+}
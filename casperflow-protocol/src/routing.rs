@@ -0,0 +1,206 @@
+use core::cmp::Reverse;
+use odra::prelude::*;
+use odra::casper_types::{PublicKey, U512};
+use crate::validator_registry::ValidatorInfo;
+
+pub const MIN_DELEGATION_AMOUNT: u64 = 500_000_000_000; // 500 CSPR, Casper's minimum delegation
+
+#[odra::odra_type]
+pub struct Allocation {
+    pub validator: PublicKey,
+    pub amount: U512,
+}
+
+#[odra::odra_type]
+pub struct StakeMove {
+    pub from: PublicKey,
+    pub to: PublicKey,
+    pub amount: U512,
+}
+
+/// A registered validator together with what the pool currently delegates to it.
+pub struct ValidatorStake {
+    pub info: ValidatorInfo,
+    pub delegated: U512,
+    pub incoming: U512,  // undelegated elsewhere, waiting to be redelegated here
+}
+
+impl ValidatorStake {
+    fn effective(&self) -> U512 {
+        self.delegated + self.incoming
+    }
+}
+
+struct Candidate {
+    validator: PublicKey,
+    weight: U512,
+    delegated: U512,
+    deficit: U512,
+    headroom: U512,
+    allocated: U512,
+}
+
+impl Candidate {
+    // Allocates up to `portion` within the cap room, unless the delegation would
+    // stay below the minimum. Returns what was taken.
+    fn take(&mut self, portion: U512, min_delegation: U512) -> U512 {
+        let portion = portion.min(self.headroom - self.allocated);
+        if portion.is_zero() || self.delegated + self.allocated + portion < min_delegation {
+            return U512::zero();
+        }
+        self.allocated += portion;
+        portion
+    }
+}
+
+/// Splits `amount` of new stake across the enabled validators.
+///
+/// Validators furthest below their target weight are filled first. Any leftover is
+/// split over the validators with cap room in proportion to their weight, and what
+/// that split cannot place at the minimum goes to the most under-allocated validators
+/// with room. No validator is pushed past its cap and no delegation is left below
+/// `MIN_DELEGATION_AMOUNT`; whatever cannot be placed stays undelegated.
+pub fn route_stake(validators: &[ValidatorStake], total_delegated: U512, amount: U512) -> Vec<Allocation> {
+    let enabled: Vec<&ValidatorStake> = validators.iter().filter(|v| v.info.enabled).collect();
+    let total_weight: u64 = enabled.iter().map(|v| v.info.weight_bp as u64).sum();
+    if total_weight == 0 || amount.is_zero() {
+        return Vec::new();
+    }
+
+    let total_after = total_delegated + amount;
+    let mut candidates: Vec<Candidate> = enabled
+        .iter()
+        .map(|v| {
+            let target = total_after * U512::from(v.info.weight_bp) / U512::from(total_weight);
+            Candidate {
+                validator: v.info.public_key.clone(),
+                weight: U512::from(v.info.weight_bp),
+                delegated: v.effective(),
+                deficit: target.saturating_sub(v.effective()),
+                headroom: v.info.cap.saturating_sub(v.effective()),
+                allocated: U512::zero(),
+            }
+        })
+        .collect();
+
+    // Most under-allocated first; the sort is stable so ties keep registry order
+    candidates.sort_by_key(|candidate| Reverse(candidate.deficit));
+
+    let min_delegation = U512::from(MIN_DELEGATION_AMOUNT);
+    let mut remaining = amount;
+    for candidate in candidates.iter_mut() {
+        let portion = candidate.deficit.min(remaining);
+        remaining -= candidate.take(portion, min_delegation);
+    }
+
+    // Repeats the weighted split over the validators still below their cap, at most
+    // once per validator
+    for _ in 0..candidates.len() {
+        let room_weight = candidates
+            .iter()
+            .filter(|candidate| candidate.allocated < candidate.headroom)
+            .fold(U512::zero(), |acc, candidate| acc + candidate.weight);
+        if room_weight.is_zero() || remaining.is_zero() {
+            break;
+        }
+        let leftover = remaining;
+        for candidate in candidates.iter_mut() {
+            if candidate.allocated < candidate.headroom {
+                remaining -= candidate.take(leftover * candidate.weight / room_weight, min_delegation);
+            }
+        }
+        if remaining == leftover {
+            break;
+        }
+    }
+
+    for candidate in candidates.iter_mut() {
+        let portion = remaining;
+        remaining -= candidate.take(portion, min_delegation);
+    }
+
+    candidates
+        .into_iter()
+        .filter(|candidate| !candidate.allocated.is_zero())
+        .map(|candidate| Allocation {
+            validator: candidate.validator,
+            amount: candidate.allocated,
+        })
+        .collect()
+}
+
+/// Plans up to `max_moves` transfers of existing delegations from validators above
+/// their target (including disabled ones, whose target is zero) to those below it.
+///
+/// Each move pairs the largest surplus with the largest deficit, so repeated calls
+/// converge on the target distribution. A move never leaves either delegation below
+/// `MIN_DELEGATION_AMOUNT`: the source keeps at least the minimum or moves whole.
+pub fn plan_rebalance(validators: &[ValidatorStake], max_moves: u32) -> Vec<StakeMove> {
+    let total_weight: u64 = validators
+        .iter()
+        .filter(|v| v.info.enabled)
+        .map(|v| v.info.weight_bp as u64)
+        .sum();
+    let total: U512 = validators.iter().fold(U512::zero(), |acc, v| acc + v.effective());
+    let targets: Vec<U512> = validators
+        .iter()
+        .map(|v| {
+            if v.info.enabled && total_weight > 0 {
+                total * U512::from(v.info.weight_bp) / U512::from(total_weight)
+            } else {
+                U512::zero()
+            }
+        })
+        .collect();
+
+    let mut effective: Vec<U512> = validators.iter().map(|v| v.effective()).collect();
+    let mut movable: Vec<U512> = validators.iter().map(|v| v.delegated).collect();
+    let min_delegation = U512::from(MIN_DELEGATION_AMOUNT);
+    let mut moves = Vec::new();
+
+    while moves.len() < max_moves as usize {
+        let surplus = |i: usize| effective[i].saturating_sub(targets[i]).min(movable[i]);
+        let deficit = |i: usize| {
+            let v = &validators[i];
+            if !v.info.enabled {
+                return U512::zero();
+            }
+            targets[i]
+                .saturating_sub(effective[i])
+                .min(v.info.cap.saturating_sub(effective[i]))
+        };
+
+        let (Some(from), Some(to)) = (
+            (0..validators.len()).max_by_key(|&i| surplus(i)),
+            (0..validators.len()).max_by_key(|&i| deficit(i)),
+        ) else {
+            break;
+        };
+        let mut amount = surplus(from).min(deficit(to));
+        let residual = movable[from] - amount;
+        if !residual.is_zero() && residual < min_delegation {
+            // Move the whole delegation if the destination has room for it, otherwise
+            // leave the minimum behind
+            amount = if movable[from] <= deficit(to) {
+                movable[from]
+            } else {
+                movable[from].saturating_sub(min_delegation)
+            };
+        }
+        // Redelegations are delegated one by one, so each must reach the minimum
+        // on top of what the destination already has delegated
+        if amount.is_zero() || movable[to] + amount < min_delegation {
+            break;
+        }
+
+        effective[from] -= amount;
+        movable[from] -= amount;
+        effective[to] += amount;
+        moves.push(StakeMove {
+            from: validators[from].info.public_key.clone(),
+            to: validators[to].info.public_key.clone(),
+            amount,
+        });
+    }
+    moves
+}
@@ -1,109 +1,680 @@
+use core::cmp::Reverse;
 use odra::prelude::*;
-use odra::casper_types::{U512, bytesrepr::ToBytes};
+use odra::casper_types::{PublicKey, U256, U512};
+use crate::access_control::{AccessControl, Role};
+use crate::fee_curve::FeeCurve;
+use crate::liquidity::LiquidityPool;
+use crate::math::{self, Rounding, RATE_PRECISION};
+use crate::reentrancy_guard::ReentrancyGuard;
+use crate::reward_oracle::RewardOracle;
+use crate::routing::{self, Allocation, StakeMove, ValidatorStake};
 use crate::token::StCSPRToken;
+use crate::validator_registry::{ValidatorInfo, ValidatorRegistry};
+use crate::withdrawal_queue::{WithdrawalQueue, WithdrawalRequest};
 
-const INSTANT_UNSTAKE_FEE_BP: u64 = 50; // 0.5% in basis points
 const MIN_STAKE_AMOUNT: u64 = 1_000_000_000; // 1 CSPR minimum
+const MAX_PROTOCOL_FEE_BP: u32 = 2_000; // 20% of rewards at most
+const LEGACY_RATE_PRECISION: u64 = 1_000_000_000; // rate scale of unversioned storage
 
-#[odra::module]
+/// Layout of the pool's storage, including the stCSPR token it embeds. Bumped with
+/// every change that needs existing storage transformed through `migrate`.
+pub const STORAGE_VERSION: u32 = 1;
+
+#[odra::odra_type]
+pub struct PendingRedelegation {
+    pub validator: PublicKey,
+    pub amount: U512,
+    pub ready_at: u64,
+}
+
+#[odra::odra_type]
+pub struct InstantWithdrawQuote {
+    pub cspr_amount: U512,
+    pub fee_bp: u32,
+    pub fee: U512,
+    pub net_amount: U512,
+}
+
+#[odra::odra_type]
+pub struct WithdrawalSummary {
+    pub paid: U512,
+    pub fee: U512,
+    pub queued: U512,
+    pub request_id: Option<u64>,
+}
+
+/// Operations that can be halted independently in an emergency.
+#[odra::odra_type]
+pub enum PausableAction {
+    Deposits,
+    InstantWithdrawals,
+    Withdrawals,
+    Transfers,
+}
+
+#[odra::event]
+pub struct StakeMoved {
+    pub from: PublicKey,
+    pub to: PublicKey,
+    pub amount: U512,
+}
+
+#[odra::event]
+pub struct ProtocolFeeCollected {
+    pub treasury: Address,
+    pub rewards: U512,
+    pub fee: U512,
+    pub shares: U512,
+}
+
+#[odra::event]
+pub struct Deposited {
+    pub account: Address,
+    pub cspr_amount: U512,
+    pub stcspr_amount: U256,
+}
+
+#[odra::event]
+pub struct WithdrawalRequested {
+    pub owner: Address,
+    pub request_id: u64,
+    pub cspr_amount: U512,
+}
+
+#[odra::event]
+pub struct WithdrawalClaimed {
+    pub owner: Address,
+    pub request_id: u64,
+    pub cspr_amount: U512,
+}
+
+#[odra::event]
+pub struct InstantWithdrawn {
+    pub account: Address,
+    pub cspr_amount: U512,
+    pub fee: U512,
+    pub net_amount: U512,
+}
+
+#[odra::event]
+pub struct RewardsCompounded {
+    pub rewards: U512,
+    pub total_staked: U512,
+}
+
+#[odra::event]
+pub struct ExchangeRateUpdated {
+    pub previous_rate: U512,
+    pub rate: U512,
+}
+
+#[odra::event]
+pub struct LiquidityAdded {
+    pub provider: Address,
+    pub amount: U512,
+    pub shares: U512,
+}
+
+#[odra::event]
+pub struct LiquidityRemoved {
+    pub provider: Address,
+    pub amount: U512,
+    pub shares: U512,
+}
+
+#[odra::event]
+pub struct PauseToggled {
+    pub action: PausableAction,
+    pub paused: bool,
+    pub sender: Address,
+}
+
+#[odra::event]
+pub struct StorageMigrated {
+    pub from_version: u32,
+    pub to_version: u32,
+}
+
+#[odra::module(events = [
+    Deposited,
+    WithdrawalRequested,
+    WithdrawalClaimed,
+    InstantWithdrawn,
+    RewardsCompounded,
+    ExchangeRateUpdated,
+    LiquidityAdded,
+    LiquidityRemoved,
+    StakeMoved,
+    ProtocolFeeCollected,
+    PauseToggled,
+    StorageMigrated
+])]
 pub struct StakingPool {
     token: SubModule<StCSPRToken>,
+    withdrawals: SubModule<WithdrawalQueue>,
+    registry: SubModule<ValidatorRegistry>,
+    oracle: SubModule<RewardOracle>,
+    liquidity: SubModule<LiquidityPool>,
+    access: SubModule<AccessControl>,
+    guard: SubModule<ReentrancyGuard>,
     total_staked: Var<U512>,
     total_rewards: Var<U512>,
     instant_pool: Var<U512>,
-    exchange_rate: Var<U512>,  // stCSPR to CSPR rate (in 10^9)
+    exchange_rate: Var<U512>,  // stCSPR to CSPR rate, scaled by RATE_PRECISION
+    delegations: Mapping<PublicKey, U512>,
+    total_delegated: Var<U512>,
+    pending_redelegations: Var<Vec<PendingRedelegation>>,
+    total_redelegating: Var<U512>,
+    treasury: Var<Address>,
+    protocol_fee_bp: Var<u32>,
+    total_protocol_fees: Var<U512>,
+    instant_fee_treasury_bp: Var<u32>,  // treasury's cut of instant unstake fees
+    instant_receivable: Var<U512>,  // stake backing stCSPR burned through the instant pool
+    instant_fee_curve: Var<FeeCurve>,
+    paused: Mapping<PausableAction, bool>,
+    deposit_cap: Var<Option<U512>>,  // limit on total_staked, none when unlimited
+    account_deposit_cap: Var<Option<U512>>,
+    account_deposits: Mapping<Address, U512>,  // net CSPR deposited per account
+    storage_version: Var<u32>,
+    total_instant_treasury_fees: Var<U512>,  // treasury's cut of instant unstake fees, in CSPR
 }
 
 #[odra::module]
 impl StakingPool {
-    pub fn init(&mut self) {
-        let pool_address = self.env().self_address();
-        self.token.init(pool_address);
+    pub fn init(&mut self, unbonding_delay: u64) {
+        self.token.init();
+        self.withdrawals.init(unbonding_delay);
+        self.oracle.init();
         self.total_staked.set(U512::zero());
         self.total_rewards.set(U512::zero());
         self.instant_pool.set(U512::zero());
-        self.exchange_rate.set(U512::from(1_000_000_000u64)); // 1:1 initially
+        self.exchange_rate.set(U512::from(RATE_PRECISION)); // 1:1 initially
+        self.access.setup_role(Role::DefaultAdmin, self.env().caller());
+        self.total_delegated.set(U512::zero());
+        self.total_redelegating.set(U512::zero());
+        self.protocol_fee_bp.set(0);
+        self.total_protocol_fees.set(U512::zero());
+        self.instant_fee_treasury_bp.set(0);
+        self.instant_receivable.set(U512::zero());
+        self.instant_fee_curve.set(FeeCurve::DEFAULT);
+        self.storage_version.set(STORAGE_VERSION);
+        self.total_instant_treasury_fees.set(U512::zero());
     }
 
     #[odra(payable)]
     pub fn deposit(&mut self) {
-        let amount = self.env().attached_value();
+        self.guard.enter();
         let caller = self.env().caller();
+        self.stake(caller, self.env().attached_value());
+        self.guard.exit();
+    }
 
-        if amount < U512::from(MIN_STAKE_AMOUNT) {
-            self.env().revert(Error::AmountTooSmall);
+    // Reverts unless at least `min_stcspr_out` is minted before `deadline` (block time)
+    #[odra(payable)]
+    pub fn deposit_with_min(&mut self, min_stcspr_out: U256, deadline: Option<u64>) {
+        self.guard.enter();
+        self.assert_before(deadline);
+        let caller = self.env().caller();
+        if self.stake(caller, self.env().attached_value()) < min_stcspr_out {
+            self.env().revert(Error::SlippageExceeded);
         }
-
-        // Calculate stCSPR to mint based on exchange rate
-        let exchange_rate = self.exchange_rate.get_or_default();
-        let stcspr_amount = self.calculate_stcspr_amount(amount, exchange_rate);
-
-        // Mint stCSPR tokens
-        self.token.mint(caller, stcspr_amount);
-
-        // Update total staked
-        let total = self.total_staked.get_or_default();
-        self.total_staked.set(total + amount);
+        self.guard.exit();
     }
 
     pub fn withdraw(&mut self, stcspr_amount: U512, instant: bool) {
+        self.guard.enter();
         let caller = self.env().caller();
-        let exchange_rate = self.exchange_rate.get_or_default();
-        let cspr_amount = self.calculate_cspr_amount(stcspr_amount, exchange_rate);
+        self.unstake(caller, stcspr_amount, instant);
+        self.guard.exit();
+    }
 
-        // Convert U512 to U256 for burn
-        let stcspr_u256 = self.u512_to_u256(stcspr_amount);
+    // Reverts unless at least `min_cspr_out` is paid out (after fees) or queued before
+    // `deadline` (block time)
+    pub fn withdraw_with_min(&mut self, stcspr_amount: U512, instant: bool, min_cspr_out: U512, deadline: Option<u64>) {
+        self.guard.enter();
+        self.assert_before(deadline);
+        let caller = self.env().caller();
+        if self.unstake(caller, stcspr_amount, instant) < min_cspr_out {
+            self.env().revert(Error::SlippageExceeded);
+        }
+        self.guard.exit();
+    }
 
-        // Burn stCSPR
-        self.token.burn(caller, stcspr_u256);
+    // Pays out instantly as much as the instant pool can cover and queues the rest
+    // as a standard withdrawal.
+    pub fn withdraw_instant_or_queue(&mut self, stcspr_amount: U512) -> WithdrawalSummary {
+        self.guard.enter();
+        let caller = self.env().caller();
+        let cspr_amount = self.burn_for_cspr(caller, stcspr_amount);
+        let total = self.total_staked.get_or_default();
+        self.total_staked.set(self.checked_sub(total, cspr_amount));
 
-        if instant {
-            // Instant unstake with fee
-            let fee = cspr_amount * U512::from(INSTANT_UNSTAKE_FEE_BP) / U512::from(10000);
-            let net_amount = cspr_amount - fee;
+        // Payout plus fee never exceeds the instant amount, so this always fits the pool
+        let instant_amount = cspr_amount.min(self.instant_pool.get_or_default());
+        let mut summary = WithdrawalSummary {
+            paid: U512::zero(),
+            fee: U512::zero(),
+            queued: cspr_amount - instant_amount,
+            request_id: None,
+        };
 
-            let pool_balance = self.instant_pool.get_or_default();
-            if pool_balance < net_amount {
-                self.env().revert(Error::InsufficientPoolLiquidity);
-            }
+        if !instant_amount.is_zero() {
+            self.assert_not_paused(PausableAction::InstantWithdrawals);
+            let quote = self.pay_instant(caller, instant_amount);
+            summary.paid = quote.net_amount;
+            summary.fee = quote.fee;
+        }
+        if !summary.queued.is_zero() {
+            self.assert_not_paused(PausableAction::Withdrawals);
+            summary.request_id = Some(self.queue_withdrawal(caller, summary.queued));
+        }
+        self.guard.exit();
+        summary
+    }
 
-            // Update pool
-            self.instant_pool.set(pool_balance - net_amount);
+    pub fn claim_withdrawal(&mut self, request_id: u64) {
+        self.guard.enter();
+        let caller = self.env().caller();
+        let amount = self.withdrawals.claim(caller, request_id);
+        self.env().emit_event(WithdrawalClaimed {
+            owner: caller,
+            request_id,
+            cspr_amount: amount,
+        });
+        self.env().transfer_tokens(&caller, &amount);
+        self.guard.exit();
+    }
 
-            // Transfer CSPR to user
-            self.env().transfer_tokens(&caller, &net_amount);
-        } else {
-            // Standard unstake - transfer immediately (in production would queue for unbonding)
-            self.env().transfer_tokens(&caller, &cspr_amount);
+    // Moves CSPR from the instant pool's matured unbonding requests back into its
+    // liquidity, at most `max_requests` of the oldest per call
+    pub fn settle_instant_pool(&mut self, max_requests: u32) {
+        self.guard.enter();
+        for _ in 0..max_requests {
+            let Some(amount) = self.withdrawals.settle_next_receivable() else {
+                break;
+            };
+            let receivable = self.instant_receivable.get_or_default();
+            self.instant_receivable.set(self.checked_sub(receivable, amount));
+            let pool_balance = self.instant_pool.get_or_default();
+            self.instant_pool.set(pool_balance + amount);
         }
-
-        // Update total staked
-        let total = self.total_staked.get_or_default();
-        self.total_staked.set(total - cspr_amount);
+        self.guard.exit();
     }
 
     #[odra(payable)]
     pub fn add_instant_liquidity(&mut self) {
+        self.guard.enter();
         let amount = self.env().attached_value();
+        let caller = self.env().caller();
+        let pool_value = self.get_instant_pool_value();
+        let shares = self.liquidity.mint_shares(caller, amount, pool_value);
+
         let pool_balance = self.instant_pool.get_or_default();
         self.instant_pool.set(pool_balance + amount);
+        self.env().emit_event(LiquidityAdded {
+            provider: caller,
+            amount,
+            shares,
+        });
+        self.guard.exit();
+    }
+
+    pub fn remove_instant_liquidity(&mut self, shares: U512) {
+        self.guard.enter();
+        let caller = self.env().caller();
+        let pool_value = self.get_instant_pool_value();
+        let amount = self.liquidity.burn_shares(caller, shares, pool_value);
+
+        let pool_balance = self.instant_pool.get_or_default();
+        if pool_balance < amount {
+            self.env().revert(Error::InsufficientPoolLiquidity);
+        }
+        self.instant_pool.set(pool_balance - amount);
+        self.env().emit_event(LiquidityRemoved {
+            provider: caller,
+            amount,
+            shares,
+        });
+        self.env().transfer_tokens(&caller, &amount);
+        self.guard.exit();
+    }
+
+    // stCSPR transfers go through the pool, which hosts the token
+    pub fn transfer(&mut self, recipient: Address, amount: U256) {
+        self.guard.enter();
+        self.assert_not_paused(PausableAction::Transfers);
+        self.token.transfer(recipient, amount);
+        self.guard.exit();
+    }
+
+    pub fn transfer_from(&mut self, owner: Address, recipient: Address, amount: U256) {
+        self.guard.enter();
+        self.assert_not_paused(PausableAction::Transfers);
+        self.token.transfer_from(owner, recipient, amount);
+        self.guard.exit();
+    }
+
+    pub fn approve(&mut self, spender: Address, amount: U256) {
+        self.guard.enter();
+        self.token.approve(spender, amount);
+        self.guard.exit();
+    }
+
+    // ERC-4626-style vault interface: stCSPR is the share, in the token's U256, CSPR
+    // the asset. Withdrawals are queued for `receiver` and claimable after the
+    // unbonding delay.
+    #[odra(payable)]
+    pub fn vault_deposit(&mut self, receiver: Address) -> U256 {
+        self.guard.enter();
+        let shares = self.stake(receiver, self.env().attached_value());
+        self.guard.exit();
+        shares
+    }
+
+    // Takes the CSPR needed for `shares` from the attached value and refunds the rest
+    #[odra(payable)]
+    pub fn vault_mint(&mut self, shares: U256, receiver: Address) -> U512 {
+        self.guard.enter();
+        let assets = self.to_cspr(self.u256_to_u512(shares), Rounding::Up);
+        let attached = self.env().attached_value();
+        if attached < assets {
+            self.env().revert(Error::InsufficientAttachedValue);
+        }
+
+        self.stake_for(receiver, assets, shares);
+        if attached > assets {
+            self.env().transfer_tokens(&self.env().caller(), &(attached - assets));
+        }
+        self.guard.exit();
+        assets
+    }
+
+    pub fn vault_withdraw(&mut self, assets: U512, receiver: Address, owner: Address) -> U256 {
+        self.guard.enter();
+        self.assert_not_paused(PausableAction::Withdrawals);
+        let shares = self.to_stcspr(assets, Rounding::Up);
+        self.spend_allowance(owner, shares);
+        self.burn_stcspr(owner, shares, assets);
+        self.queue_redemption(receiver, assets);
+        self.guard.exit();
+        self.u512_to_u256(shares)
+    }
+
+    pub fn vault_redeem(&mut self, shares: U256, receiver: Address, owner: Address) -> U512 {
+        self.guard.enter();
+        self.assert_not_paused(PausableAction::Withdrawals);
+        let shares = self.u256_to_u512(shares);
+        self.spend_allowance(owner, shares);
+        let assets = self.burn_for_cspr(owner, shares);
+        self.queue_redemption(receiver, assets);
+        self.guard.exit();
+        assets
     }
 
     pub fn compound_rewards(&mut self) {
+        self.guard.enter();
+        self.compound();
+        self.guard.exit();
+    }
+
+    // Credits delegation rewards, plus any CSPR in the purse beyond what the pool owes,
+    // and compounds them into the exchange rate. Slashing losses reduce the stake.
+    pub fn sync_rewards(&mut self) {
+        self.guard.enter();
+        let surplus = self.purse_surplus();
+        let mut gained = U512::zero();
+        let mut lost = U512::zero();
+
+        for info in self.registry.all() {
+            let validator = info.public_key;
+            let recorded = self.get_delegated_amount(validator.clone());
+            if recorded.is_zero() {
+                continue;
+            }
+            let actual = self.env().delegated_amount(validator.clone());
+            if actual > recorded {
+                gained += actual - recorded;
+                self.record_delegation(validator, actual - recorded, true);
+            } else if actual < recorded {
+                lost += recorded - actual;
+                self.record_delegation(validator, recorded - actual, false);
+            }
+        }
+
+        if !lost.is_zero() {
+            let total = self.total_staked.get_or_default();
+            self.total_staked.set(total.saturating_sub(lost));
+            self.update_exchange_rate();
+        }
+
+        // Rewards credited through `report_rewards` are already recorded on the
+        // delegations, so they are not counted twice
         let rewards = self.total_rewards.get_or_default();
-        if rewards == U512::zero() {
-            return;
+        self.total_rewards.set(rewards + surplus + gained);
+        self.compound();
+        self.guard.exit();
+    }
+
+    pub fn report_rewards(&mut self, era_id: u64, amount: U512) {
+        self.guard.enter();
+        let caller = self.env().caller();
+        self.access.check_role(Role::Reporter, caller);
+        let total_delegated = self.get_total_delegated();
+        if let Some(rewards) = self.oracle.submit(caller, era_id, amount, total_delegated) {
+            self.record_reported_rewards(rewards, total_delegated);
+            let total = self.total_rewards.get_or_default();
+            self.total_rewards.set(total + rewards);
+            self.compound();
         }
+        self.guard.exit();
+    }
 
-        // Add rewards to total staked (simulating restaking)
-        let total = self.total_staked.get_or_default();
-        self.total_staked.set(total + rewards);
+    // Role administration
+    pub fn grant_role(&mut self, role: Role, account: Address) {
+        self.guard.enter();
+        let caller = self.env().caller();
+        self.access.grant_role(role, account, caller);
+        self.guard.exit();
+    }
 
-        // Update exchange rate to reflect compounded value
-        self.update_exchange_rate();
+    pub fn revoke_role(&mut self, role: Role, account: Address) {
+        self.guard.enter();
+        let caller = self.env().caller();
+        self.access.revoke_role(role, account, caller);
+        self.guard.exit();
+    }
 
-        // Reset rewards
-        self.total_rewards.set(U512::zero());
+    pub fn renounce_role(&mut self, role: Role) {
+        self.guard.enter();
+        let caller = self.env().caller();
+        self.access.renounce_role(role, caller);
+        self.guard.exit();
+    }
+
+    // Brings storage written by an older contract version up to `STORAGE_VERSION`,
+    // run by the admin once the new code is installed
+    pub fn migrate(&mut self, from_version: u32) {
+        self.guard.enter();
+        self.assert_role(Role::DefaultAdmin);
+        if from_version != self.get_storage_version() || from_version >= STORAGE_VERSION {
+            self.env().revert(Error::InvalidStorageVersion);
+        }
+        for version in from_version..STORAGE_VERSION {
+            self.migrate_from(version);
+        }
+        self.storage_version.set(STORAGE_VERSION);
+        self.env().emit_event(StorageMigrated {
+            from_version,
+            to_version: STORAGE_VERSION,
+        });
+        self.guard.exit();
+    }
+
+    // Emergency controls
+    pub fn pause(&mut self, action: PausableAction) {
+        self.guard.enter();
+        self.set_paused(action, true);
+        self.guard.exit();
+    }
+
+    pub fn unpause(&mut self, action: PausableAction) {
+        self.guard.enter();
+        self.set_paused(action, false);
+        self.guard.exit();
+    }
+
+    // Deposit limits, `None` lifts the cap
+    pub fn set_deposit_cap(&mut self, cap: Option<U512>) {
+        self.guard.enter();
+        self.assert_role(Role::DefaultAdmin);
+        self.deposit_cap.set(cap);
+        self.guard.exit();
+    }
+
+    pub fn set_account_deposit_cap(&mut self, cap: Option<U512>) {
+        self.guard.enter();
+        self.assert_role(Role::DefaultAdmin);
+        self.account_deposit_cap.set(cap);
+        self.guard.exit();
+    }
+
+    // Protocol fee administration
+    pub fn set_treasury(&mut self, treasury: Address) {
+        self.guard.enter();
+        self.assert_role(Role::Treasury);
+        self.treasury.set(treasury);
+        self.guard.exit();
+    }
+
+    pub fn set_protocol_fee(&mut self, fee_bp: u32) {
+        self.guard.enter();
+        self.assert_role(Role::Treasury);
+        if fee_bp > MAX_PROTOCOL_FEE_BP {
+            self.env().revert(Error::FeeTooHigh);
+        }
+        self.protocol_fee_bp.set(fee_bp);
+        self.guard.exit();
+    }
+
+    pub fn set_instant_fee_treasury_share(&mut self, share_bp: u32) {
+        self.guard.enter();
+        self.assert_role(Role::Treasury);
+        if share_bp > 10_000 {
+            self.env().revert(Error::FeeTooHigh);
+        }
+        self.instant_fee_treasury_bp.set(share_bp);
+        self.guard.exit();
+    }
+
+    pub fn set_instant_fee_curve(&mut self, curve: FeeCurve) {
+        self.guard.enter();
+        self.assert_role(Role::Treasury);
+        if !curve.is_valid() {
+            self.env().revert(Error::InvalidFeeCurve);
+        }
+        self.instant_fee_curve.set(curve);
+        self.guard.exit();
+    }
+
+    // Reward oracle administration
+    pub fn set_reporter_quorum(&mut self, quorum: u32) {
+        self.guard.enter();
+        self.assert_role(Role::DefaultAdmin);
+        self.oracle.set_quorum(quorum);
+        self.guard.exit();
+    }
+
+    pub fn set_max_reward_apr(&mut self, max_apr_bp: u32) {
+        self.guard.enter();
+        self.assert_role(Role::DefaultAdmin);
+        self.oracle.set_max_apr_bp(max_apr_bp);
+        self.guard.exit();
+    }
+
+    /// Anchors reward reports at the current chain era; the first report must be
+    /// for a later one. Reverts once a report has been accepted.
+    pub fn set_reward_start_era(&mut self, era_id: u64) {
+        self.guard.enter();
+        self.assert_role(Role::DefaultAdmin);
+        self.oracle.set_start_era(era_id);
+        self.guard.exit();
+    }
+
+    // Validator administration
+    pub fn add_validator(&mut self, public_key: PublicKey, weight_bp: u32, cap: U512) {
+        self.guard.enter();
+        self.assert_role(Role::DefaultAdmin);
+        self.registry.add(public_key, weight_bp, cap);
+        self.guard.exit();
+    }
+
+    pub fn enable_validator(&mut self, public_key: PublicKey) {
+        self.guard.enter();
+        self.assert_role(Role::DefaultAdmin);
+        self.registry.set_enabled(public_key, true);
+        self.guard.exit();
+    }
+
+    pub fn disable_validator(&mut self, public_key: PublicKey) {
+        self.guard.enter();
+        self.assert_role(Role::DefaultAdmin);
+        self.registry.set_enabled(public_key, false);
+        self.guard.exit();
+    }
+
+    pub fn set_validator_weight(&mut self, public_key: PublicKey, weight_bp: u32) {
+        self.guard.enter();
+        self.assert_role(Role::DefaultAdmin);
+        self.registry.set_weight(public_key, weight_bp);
+        self.guard.exit();
+    }
+
+    pub fn set_validator_cap(&mut self, public_key: PublicKey, cap: U512) {
+        self.guard.enter();
+        self.assert_role(Role::DefaultAdmin);
+        self.registry.set_cap(public_key, cap);
+        self.guard.exit();
+    }
+
+    pub fn remove_validator(&mut self, public_key: PublicKey) {
+        self.guard.enter();
+        self.assert_role(Role::DefaultAdmin);
+        if !self.get_delegated_amount(public_key.clone()).is_zero() {
+            self.env().revert(Error::ValidatorHasDelegations);
+        }
+        self.registry.remove(public_key);
+        self.guard.exit();
+    }
+
+    // Moves stake toward the target weights, at most `max_moves` operations per call.
+    // Undelegated stake is redelegated by a later call once it has unbonded.
+    pub fn rebalance(&mut self, max_moves: u32) {
+        self.guard.enter();
+        self.assert_role(Role::Operator);
+
+        let completed = self.complete_redelegations(max_moves);
+        let ready_at = self.env().get_block_time() + self.withdrawals.unbonding_delay();
+        let mut pending = self.pending_redelegations.get_or_default();
+
+        for stake_move in self.preview_rebalance(max_moves - completed) {
+            self.env().undelegate(stake_move.from.clone(), stake_move.amount);
+            self.record_delegation(stake_move.from.clone(), stake_move.amount, false);
+            pending.push(PendingRedelegation {
+                validator: stake_move.to.clone(),
+                amount: stake_move.amount,
+                ready_at,
+            });
+            let redelegating = self.get_total_redelegating();
+            self.total_redelegating.set(redelegating + stake_move.amount);
+
+            self.env().emit_event(StakeMoved {
+                from: stake_move.from,
+                to: stake_move.to,
+                amount: stake_move.amount,
+            });
+        }
+        self.pending_redelegations.set(pending);
+        self.guard.exit();
     }
 
     // View functions
@@ -115,53 +686,1983 @@ impl StakingPool {
         self.exchange_rate.get_or_default()
     }
 
+    // Zero for storage written before versioning
+    pub fn get_storage_version(&self) -> u32 {
+        self.storage_version.get_or_default()
+    }
+
     pub fn get_instant_pool_balance(&self) -> U512 {
         self.instant_pool.get_or_default()
     }
 
-    pub fn get_stcspr_balance(&self, account: Address) -> odra::casper_types::U256 {
-        self.token.balance_of(account)
+    pub fn get_instant_receivable(&self) -> U512 {
+        self.instant_receivable.get_or_default()
     }
 
-    pub fn get_stcspr_token_name(&self) -> String {
-        self.token.name()
+    // Total value backing the LP shares
+    pub fn get_instant_pool_value(&self) -> U512 {
+        self.instant_pool.get_or_default() + self.get_instant_receivable()
     }
 
-    // Helper functions
-    fn calculate_stcspr_amount(&self, cspr_amount: U512, exchange_rate: U512) -> odra::casper_types::U256 {
-        let result = (cspr_amount * U512::from(1_000_000_000u64)) / exchange_rate;
-        self.u512_to_u256(result)
+    pub fn get_lp_shares(&self, provider: Address) -> U512 {
+        self.liquidity.shares_of(provider)
     }
 
-    fn calculate_cspr_amount(&self, stcspr_amount: U512, exchange_rate: U512) -> U512 {
-        (stcspr_amount * exchange_rate) / U512::from(1_000_000_000u64)
+    pub fn get_total_lp_shares(&self) -> U512 {
+        self.liquidity.total_shares()
     }
 
-    fn update_exchange_rate(&mut self) {
-        let total_staked = self.total_staked.get_or_default();
-        let total_supply_u256 = self.token.total_supply();
-        let total_supply = self.u256_to_u512(total_supply_u256);
+    pub fn get_unbonding_delay(&self) -> u64 {
+        self.withdrawals.unbonding_delay()
+    }
 
-        if total_supply > U512::zero() {
-            let new_rate = (total_staked * U512::from(1_000_000_000u64)) / total_supply;
-            self.exchange_rate.set(new_rate);
+    pub fn get_total_pending_withdrawals(&self) -> U512 {
+        self.withdrawals.total_pending()
+    }
+
+    pub fn get_withdrawal_request(&self, request_id: u64) -> Option<WithdrawalRequest> {
+        self.withdrawals.get(request_id)
+    }
+
+    pub fn get_pending_withdrawals(&self, owner: Address) -> Vec<WithdrawalRequest> {
+        self.withdrawals.pending_of(owner)
+    }
+
+    /// The instant pool's unbonding requests not settled yet, at most `max_requests`
+    /// of them, oldest first.
+    pub fn get_pending_receivables(&self, max_requests: u32) -> Vec<WithdrawalRequest> {
+        self.withdrawals.pending_receivables(max_requests)
+    }
+
+    pub fn has_role(&self, role: Role, account: Address) -> bool {
+        self.access.has_role(role, account)
+    }
+
+    pub fn get_validator(&self, public_key: PublicKey) -> Option<ValidatorInfo> {
+        self.registry.get(public_key)
+    }
+
+    pub fn get_validators(&self) -> Vec<ValidatorInfo> {
+        self.registry.all()
+    }
+
+    pub fn get_delegated_amount(&self, validator: PublicKey) -> U512 {
+        self.delegations.get(&validator).unwrap_or_default()
+    }
+
+    pub fn get_total_delegated(&self) -> U512 {
+        self.total_delegated.get_or_default()
+    }
+
+    // Delegation balance as reported by the auction, including accrued rewards
+    pub fn get_delegation_balance(&self, validator: PublicKey) -> U512 {
+        self.env().delegated_amount(validator)
+    }
+
+    pub fn is_paused(&self, action: PausableAction) -> bool {
+        self.paused.get(&action).unwrap_or_default()
+    }
+
+    pub fn get_deposit_cap(&self) -> Option<U512> {
+        self.deposit_cap.get_or_default()
+    }
+
+    pub fn get_account_deposit_cap(&self) -> Option<U512> {
+        self.account_deposit_cap.get_or_default()
+    }
+
+    pub fn get_account_deposits(&self, account: Address) -> U512 {
+        self.account_deposits.get(&account).unwrap_or_default()
+    }
+
+    // Room left under the global cap, `None` when deposits are uncapped
+    pub fn get_remaining_deposit_capacity(&self) -> Option<U512> {
+        self.get_deposit_cap().map(|cap| cap.saturating_sub(self.get_total_staked()))
+    }
+
+    // Room left for `account`, the lower of its own and the global remaining capacity
+    pub fn get_remaining_account_capacity(&self, account: Address) -> Option<U512> {
+        let account_room = self
+            .get_account_deposit_cap()
+            .map(|cap| cap.saturating_sub(self.get_account_deposits(account)));
+        match (account_room, self.get_remaining_deposit_capacity()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
         }
     }
 
-    fn u512_to_u256(&self, value: U512) -> odra::casper_types::U256 {
-        let bytes = value.to_bytes().unwrap_or_default();
-        odra::casper_types::U256::from_little_endian(&bytes)
+    pub fn get_treasury(&self) -> Option<Address> {
+        self.treasury.get()
     }
 
-    fn u256_to_u512(&self, value: odra::casper_types::U256) -> U512 {
-        let mut bytes = [0u8; 32];
-        value.to_little_endian(&mut bytes);
-        U512::from_little_endian(&bytes)
+    pub fn get_protocol_fee(&self) -> u32 {
+        self.protocol_fee_bp.get_or_default()
     }
-}
 
-#[odra::odra_error]
-pub enum Error {
-    AmountTooSmall = 100,
-    InsufficientPoolLiquidity = 101,
+    pub fn get_total_protocol_fees(&self) -> U512 {
+        self.total_protocol_fees.get_or_default()
+    }
+
+    pub fn get_total_instant_treasury_fees(&self) -> U512 {
+        self.total_instant_treasury_fees.get_or_default()
+    }
+
+    pub fn get_instant_fee_treasury_share(&self) -> u32 {
+        self.instant_fee_treasury_bp.get_or_default()
+    }
+
+    pub fn get_instant_fee_curve(&self) -> FeeCurve {
+        self.instant_fee_curve.get().unwrap_or(FeeCurve::DEFAULT)
+    }
+
+    // Previews share the conversion code of `deposit` and `withdraw`, so they match execution
+    pub fn convert_to_stcspr(&self, cspr_amount: U512) -> U256 {
+        self.calculate_stcspr_amount(cspr_amount)
+    }
+
+    pub fn convert_to_cspr(&self, stcspr_amount: U512) -> U512 {
+        self.calculate_cspr_amount(stcspr_amount)
+    }
+
+    pub fn convert_to_shares(&self, assets: U512) -> U256 {
+        self.calculate_stcspr_amount(assets)
+    }
+
+    pub fn convert_to_assets(&self, shares: U256) -> U512 {
+        self.calculate_cspr_amount(self.u256_to_u512(shares))
+    }
+
+    // Native CSPR has no contract address
+    pub fn asset(&self) -> Option<Address> {
+        None
+    }
+
+    pub fn total_assets(&self) -> U512 {
+        self.get_total_staked()
+    }
+
+    pub fn max_deposit(&self, receiver: Address) -> U512 {
+        if self.is_paused(PausableAction::Deposits) {
+            return U512::zero();
+        }
+        self.get_remaining_account_capacity(receiver).unwrap_or(U512::MAX)
+    }
+
+    pub fn max_redeem(&self, owner: Address) -> U256 {
+        if self.is_paused(PausableAction::Withdrawals) {
+            return U256::zero();
+        }
+        self.token.balance_of(owner)
+    }
+
+    pub fn get_stcspr_allowance(&self, owner: Address, spender: Address) -> U256 {
+        self.token.allowance(owner, spender)
+    }
+
+    pub fn preview_deposit(&self, cspr_amount: U512) -> U256 {
+        self.calculate_stcspr_amount(cspr_amount)
+    }
+
+    // CSPR paid out after fees for an instant withdrawal, otherwise the amount queued
+    pub fn preview_withdraw(&self, stcspr_amount: U512, instant: bool) -> U512 {
+        let cspr_amount = self.calculate_cspr_amount(stcspr_amount);
+        if instant {
+            self.quote_instant(cspr_amount).net_amount
+        } else {
+            cspr_amount
+        }
+    }
+
+    pub fn quote_instant_withdraw(&self, stcspr_amount: U512) -> InstantWithdrawQuote {
+        let cspr_amount = self.calculate_cspr_amount(stcspr_amount);
+        self.quote_instant(cspr_amount)
+    }
+
+    pub fn get_reporter_quorum(&self) -> u32 {
+        self.oracle.quorum()
+    }
+
+    pub fn get_max_reward_apr(&self) -> u32 {
+        self.oracle.max_apr_bp()
+    }
+
+    pub fn get_last_reported_era(&self) -> u64 {
+        self.oracle.last_era()
+    }
+
+    pub fn get_total_redelegating(&self) -> U512 {
+        self.total_redelegating.get_or_default()
+    }
+
+    pub fn get_pending_redelegations(&self) -> Vec<PendingRedelegation> {
+        self.pending_redelegations.get_or_default()
+    }
+
+    pub fn preview_routing(&self, amount: U512) -> Vec<Allocation> {
+        let total = self.get_total_delegated() + self.get_total_redelegating();
+        routing::route_stake(&self.validator_stakes(), total, self.idle_stake() + amount)
+    }
+
+    pub fn preview_rebalance(&self, max_moves: u32) -> Vec<StakeMove> {
+        routing::plan_rebalance(&self.validator_stakes(), max_moves)
+    }
+
+    pub fn get_stcspr_balance(&self, account: Address) -> U256 {
+        self.token.balance_of(account)
+    }
+
+    pub fn get_stcspr_token_name(&self) -> String {
+        self.token.name()
+    }
+
+    // Helper functions
+    // Deposits and redemptions round down, so rounding never pays out more than the pool holds
+    fn calculate_stcspr_amount(&self, cspr_amount: U512) -> U256 {
+        self.u512_to_u256(self.to_stcspr(cspr_amount, Rounding::Down))
+    }
+
+    // The virtual offset can value the last shares above what the pool holds after a loss
+    fn calculate_cspr_amount(&self, stcspr_amount: U512) -> U512 {
+        self.to_cspr(stcspr_amount, Rounding::Down).min(self.get_total_staked())
+    }
+
+    fn to_stcspr(&self, cspr_amount: U512, rounding: Rounding) -> U512 {
+        let supply = self.u256_to_u512(self.token.total_supply());
+        self.unwrap_math(math::to_shares(cspr_amount, self.get_total_staked(), supply, rounding))
+    }
+
+    fn to_cspr(&self, stcspr_amount: U512, rounding: Rounding) -> U512 {
+        let supply = self.u256_to_u512(self.token.total_supply());
+        self.unwrap_math(math::to_assets(stcspr_amount, self.get_total_staked(), supply, rounding))
+    }
+
+    // Utilization is the share of the instant pool's value locked up in receivables
+    // once this withdrawal has been paid out.
+    fn quote_instant(&self, cspr_amount: U512) -> InstantWithdrawQuote {
+        let pool_value = self.get_instant_pool_value();
+        let utilization_bp = if pool_value.is_zero() {
+            10_000
+        } else {
+            let locked = self.get_instant_receivable() + cspr_amount;
+            (locked * U512::from(10_000) / pool_value).min(U512::from(10_000)).as_u32()
+        };
+
+        let fee_bp = self.get_instant_fee_curve().fee_bp(utilization_bp);
+        let fee = cspr_amount * U512::from(fee_bp) / U512::from(10000);
+        InstantWithdrawQuote {
+            cspr_amount,
+            fee_bp,
+            fee,
+            net_amount: cspr_amount - fee,
+        }
+    }
+
+    // Adds pending rewards to the stake, takes the protocol fee and restakes them
+    fn compound(&mut self) {
+        let rewards = self.total_rewards.get_or_default();
+        if rewards == U512::zero() {
+            return;
+        }
+
+        // Add rewards to total staked and restake them
+        let total = self.total_staked.get_or_default() + rewards;
+        self.total_staked.set(total);
+        self.collect_protocol_fee(rewards);
+        self.delegate_idle_stake();
+
+        // Update exchange rate to reflect compounded value
+        self.update_exchange_rate();
+
+        // Reset rewards
+        self.total_rewards.set(U512::zero());
+        self.env().emit_event(RewardsCompounded {
+            rewards,
+            total_staked: total,
+        });
+    }
+
+    // Mints stCSPR for `amount` of CSPR, returns the amount minted
+    fn stake(&mut self, account: Address, amount: U512) -> U256 {
+        // Calculate stCSPR to mint based on exchange rate
+        let stcspr_amount = self.calculate_stcspr_amount(amount);
+        self.stake_for(account, amount, stcspr_amount);
+        stcspr_amount
+    }
+
+    // Takes `amount` of CSPR for exactly `stcspr_amount` of newly minted stCSPR
+    fn stake_for(&mut self, account: Address, amount: U512, stcspr_amount: U256) {
+        self.assert_not_paused(PausableAction::Deposits);
+
+        if amount < U512::from(MIN_STAKE_AMOUNT) {
+            self.env().revert(Error::AmountTooSmall);
+        }
+        self.record_deposit(account, amount);
+
+        // Mint stCSPR tokens
+        self.token.mint(account, stcspr_amount);
+
+        // Update total staked
+        let total = self.total_staked.get_or_default();
+        self.total_staked.set(self.checked_add(total, amount));
+
+        // Route the deposit to validators
+        self.delegate_idle_stake();
+
+        self.env().emit_event(Deposited {
+            account,
+            cspr_amount: amount,
+            stcspr_amount,
+        });
+    }
+
+    // Burns `stcspr_amount`, returns the CSPR paid out after fees or queued
+    fn unstake(&mut self, account: Address, stcspr_amount: U512, instant: bool) -> U512 {
+        self.assert_not_paused(if instant {
+            PausableAction::InstantWithdrawals
+        } else {
+            PausableAction::Withdrawals
+        });
+        let cspr_amount = self.burn_for_cspr(account, stcspr_amount);
+        let total = self.total_staked.get_or_default();
+        self.total_staked.set(self.checked_sub(total, cspr_amount));
+
+        if instant {
+            self.pay_instant(account, cspr_amount).net_amount
+        } else {
+            // Standard unstake - undelegate and queue until the unbonding delay has passed
+            self.queue_withdrawal(account, cspr_amount);
+            cspr_amount
+        }
+    }
+
+    fn burn_for_cspr(&mut self, owner: Address, stcspr_amount: U512) -> U512 {
+        let cspr_amount = self.calculate_cspr_amount(stcspr_amount);
+        self.burn_stcspr(owner, stcspr_amount, cspr_amount);
+        cspr_amount
+    }
+
+    fn burn_stcspr(&mut self, owner: Address, stcspr_amount: U512, cspr_amount: U512) {
+        // Convert U512 to U256 for burn
+        let stcspr_u256 = self.u512_to_u256(stcspr_amount);
+
+        // Burn stCSPR
+        self.token.burn(owner, stcspr_u256);
+
+        let deposited = self.get_account_deposits(owner);
+        self.account_deposits.set(&owner, deposited.saturating_sub(cspr_amount));
+    }
+
+    // Lets a spender other than `owner` redeem against the owner's stCSPR allowance
+    fn spend_allowance(&mut self, owner: Address, stcspr_amount: U512) {
+        let spender = self.env().caller();
+        if spender != owner {
+            let amount = self.u512_to_u256(stcspr_amount);
+            self.token.spend_allowance(owner, spender, amount);
+        }
+    }
+
+    fn queue_redemption(&mut self, receiver: Address, cspr_amount: U512) {
+        let total = self.total_staked.get_or_default();
+        self.total_staked.set(self.checked_sub(total, cspr_amount));
+        self.queue_withdrawal(receiver, cspr_amount);
+    }
+
+    // Instant unstake with a utilization-based fee, split between LPs and the treasury
+    fn pay_instant(&mut self, recipient: Address, cspr_amount: U512) -> InstantWithdrawQuote {
+        let quote = self.quote_instant(cspr_amount);
+        let treasury = self.treasury.get();
+        let treasury_fee = match treasury {
+            Some(_) => quote.fee * U512::from(self.get_instant_fee_treasury_share()) / U512::from(10000),
+            None => U512::zero(),
+        };
+
+        let pool_balance = self.instant_pool.get_or_default();
+        if pool_balance < quote.net_amount + treasury_fee {
+            self.env().revert(Error::InsufficientPoolLiquidity);
+        }
+
+        // Update pool - it pays out now and takes over the stake backing the burned
+        // stCSPR, so LPs gain the part of the fee not sent to the treasury
+        self.instant_pool.set(pool_balance - quote.net_amount - treasury_fee);
+        let receivable = self.instant_receivable.get_or_default();
+        self.instant_receivable.set(receivable + cspr_amount);
+
+        // Unbond that stake on the instant pool's behalf, see `settle_instant_pool`
+        let pool_address = self.env().self_address();
+        self.undelegate_stake(cspr_amount);
+        self.withdrawals.enqueue_receivable(pool_address, cspr_amount);
+
+        let treasury = treasury.filter(|_| !treasury_fee.is_zero());
+        if treasury.is_some() {
+            let collected = self.get_total_instant_treasury_fees();
+            self.total_instant_treasury_fees.set(self.checked_add(collected, treasury_fee));
+        }
+        self.env().emit_event(InstantWithdrawn {
+            account: recipient,
+            cspr_amount,
+            fee: quote.fee,
+            net_amount: quote.net_amount,
+        });
+
+        // Transfer CSPR to user and treasury once all state is updated
+        self.env().transfer_tokens(&recipient, &quote.net_amount);
+        if let Some(treasury) = treasury {
+            self.env().transfer_tokens(&treasury, &treasury_fee);
+        }
+        quote
+    }
+
+    fn queue_withdrawal(&mut self, owner: Address, cspr_amount: U512) -> u64 {
+        self.undelegate_stake(cspr_amount);
+        let request_id = self.withdrawals.enqueue(owner, cspr_amount);
+        self.env().emit_event(WithdrawalRequested {
+            owner,
+            request_id,
+            cspr_amount,
+        });
+        request_id
+    }
+
+    fn assert_role(&self, role: Role) {
+        self.access.check_role(role, self.env().caller());
+    }
+
+    fn record_deposit(&mut self, account: Address, amount: U512) {
+        if let Some(cap) = self.get_deposit_cap() {
+            if self.checked_add(self.get_total_staked(), amount) > cap {
+                self.env().revert(Error::DepositCapExceeded);
+            }
+        }
+
+        let deposited = self.checked_add(self.get_account_deposits(account), amount);
+        if let Some(cap) = self.get_account_deposit_cap() {
+            if deposited > cap {
+                self.env().revert(Error::AccountCapExceeded);
+            }
+        }
+        self.account_deposits.set(&account, deposited);
+    }
+
+    fn assert_before(&self, deadline: Option<u64>) {
+        if deadline.is_some_and(|deadline| self.env().get_block_time() > deadline) {
+            self.env().revert(Error::DeadlineExpired);
+        }
+    }
+
+    fn assert_not_paused(&self, action: PausableAction) {
+        if !self.is_paused(action.clone()) {
+            return;
+        }
+        self.env().revert(match action {
+            PausableAction::Deposits => Error::DepositsPaused,
+            PausableAction::InstantWithdrawals => Error::InstantWithdrawalsPaused,
+            PausableAction::Withdrawals => Error::WithdrawalsPaused,
+            PausableAction::Transfers => Error::TransfersPaused,
+        });
+    }
+
+    fn set_paused(&mut self, action: PausableAction, paused: bool) {
+        self.assert_role(Role::Pauser);
+        if self.is_paused(action.clone()) == paused {
+            return;
+        }
+        self.paused.set(&action, paused);
+        self.env().emit_event(PauseToggled {
+            action,
+            paused,
+            sender: self.env().caller(),
+        });
+    }
+
+    // Staked CSPR still sitting in the contract purse, e.g. deposits too small
+    // to meet the minimum delegation on their own.
+    fn idle_stake(&self) -> U512 {
+        let committed = self.get_total_delegated() + self.get_total_redelegating();
+        self.total_staked.get_or_default().saturating_sub(committed)
+    }
+
+    // Purse balance not accounted for by idle stake, instant liquidity or CSPR owed
+    // to pending withdrawals and redelegations.
+    fn purse_surplus(&self) -> U512 {
+        let owed = self.idle_stake()
+            + self.instant_pool.get_or_default()
+            + self.withdrawals.total_pending()
+            + self.get_total_redelegating();
+        self.env().self_balance().saturating_sub(owed)
+    }
+
+    fn validator_stakes(&self) -> Vec<ValidatorStake> {
+        let pending = self.get_pending_redelegations();
+        self.registry
+            .all()
+            .into_iter()
+            .map(|info| ValidatorStake {
+                delegated: self.get_delegated_amount(info.public_key.clone()),
+                incoming: pending
+                    .iter()
+                    .filter(|redelegation| redelegation.validator == info.public_key)
+                    .fold(U512::zero(), |acc, redelegation| acc + redelegation.amount),
+                info,
+            })
+            .collect()
+    }
+
+    // Redelegates unbonded stake from earlier rebalance moves. A destination that was
+    // disabled or removed in the meantime, or that the amount would leave below the
+    // minimum delegation, is skipped and the CSPR is left idle.
+    fn complete_redelegations(&mut self, max_moves: u32) -> u32 {
+        let now = self.env().get_block_time();
+        let mut completed = 0;
+        let mut still_pending = Vec::new();
+
+        for redelegation in self.get_pending_redelegations() {
+            if completed == max_moves || redelegation.ready_at > now {
+                still_pending.push(redelegation);
+                continue;
+            }
+            completed += 1;
+
+            let redelegating = self.get_total_redelegating();
+            self.total_redelegating.set(self.checked_sub(redelegating, redelegation.amount));
+            let enabled = self
+                .registry
+                .get(redelegation.validator.clone())
+                .is_some_and(|info| info.enabled);
+            let delegated = self.get_delegated_amount(redelegation.validator.clone());
+            let min = U512::from(routing::MIN_DELEGATION_AMOUNT);
+            if enabled && delegated + redelegation.amount >= min {
+                self.env().delegate(redelegation.validator.clone(), redelegation.amount);
+                self.record_delegation(redelegation.validator, redelegation.amount, true);
+            }
+        }
+
+        self.pending_redelegations.set(still_pending);
+        completed
+    }
+
+    fn delegate_idle_stake(&mut self) {
+        for allocation in self.preview_routing(U512::zero()) {
+            self.env().delegate(allocation.validator.clone(), allocation.amount);
+            self.record_delegation(allocation.validator, allocation.amount, true);
+        }
+    }
+
+    // Undelegates from the largest delegations first. Any part of `amount` not
+    // covered by delegations is expected to already sit in the contract purse.
+    // Callers take `amount` off `total_staked` beforehand.
+    //
+    // A delegation is never left below the minimum: it keeps at least
+    // `MIN_DELEGATION_AMOUNT` or is undelegated whole. CSPR undelegated beyond
+    // `amount` unbonds as a redelegation back to the same validator, which
+    // `complete_redelegations` leaves idle if it cannot be delegated there.
+    fn undelegate_stake(&mut self, amount: U512) {
+        let min = U512::from(routing::MIN_DELEGATION_AMOUNT);
+        let mut validators: Vec<PublicKey> = self
+            .registry
+            .all()
+            .into_iter()
+            .map(|info| info.public_key)
+            .collect();
+        validators.sort_by_key(|validator| Reverse(self.get_delegated_amount(validator.clone())));
+
+        let committed = self.get_total_delegated() + self.get_total_redelegating();
+        let idle = (self.total_staked.get_or_default() + amount).saturating_sub(committed);
+
+        let mut remaining = amount;
+        for validator in validators.iter() {
+            if remaining.is_zero() {
+                break;
+            }
+            let delegated = self.get_delegated_amount(validator.clone());
+            let mut portion = remaining.min(delegated);
+            let residual = delegated - portion;
+            if !residual.is_zero() && residual < min {
+                portion = delegated.saturating_sub(min);
+            }
+            if portion.is_zero() {
+                continue;
+            }
+            self.env().undelegate(validator.clone(), portion);
+            self.record_delegation(validator.clone(), portion, false);
+            remaining -= portion;
+        }
+
+        // Whatever idle stake cannot cover is taken from the delegations kept at
+        // the minimum, whole
+        let mut shortfall = remaining.saturating_sub(idle);
+        for validator in validators {
+            if shortfall.is_zero() {
+                break;
+            }
+            let delegated = self.get_delegated_amount(validator.clone());
+            if delegated.is_zero() {
+                continue;
+            }
+            self.env().undelegate(validator.clone(), delegated);
+            self.record_delegation(validator.clone(), delegated, false);
+            if delegated > shortfall {
+                self.redelegate_excess(validator, delegated - shortfall);
+                break;
+            }
+            shortfall -= delegated;
+        }
+    }
+
+    fn redelegate_excess(&mut self, validator: PublicKey, amount: U512) {
+        let ready_at = self.env().get_block_time() + self.withdrawals.unbonding_delay();
+        let mut pending = self.pending_redelegations.get_or_default();
+        pending.push(PendingRedelegation {
+            validator,
+            amount,
+            ready_at,
+        });
+        self.pending_redelegations.set(pending);
+        let redelegating = self.get_total_redelegating();
+        self.total_redelegating.set(self.checked_add(redelegating, amount));
+    }
+
+    fn record_delegation(&mut self, validator: PublicKey, amount: U512, increase: bool) {
+        let delegated = self.get_delegated_amount(validator.clone());
+        let total = self.total_delegated.get_or_default();
+        if increase {
+            self.delegations.set(&validator, self.checked_add(delegated, amount));
+            self.total_delegated.set(self.checked_add(total, amount));
+        } else {
+            self.delegations.set(&validator, self.checked_sub(delegated, amount));
+            self.total_delegated.set(self.checked_sub(total, amount));
+        }
+    }
+
+    // Splits reported rewards over the delegations that earned them, pro rata, so
+    // withdrawals undelegate them like the rest of the stake. The oracle bounds
+    // rewards by `total_delegated`, so none are reported while nothing is delegated.
+    fn record_reported_rewards(&mut self, rewards: U512, total_delegated: U512) {
+        let delegated: Vec<(PublicKey, U512)> = self
+            .registry
+            .all()
+            .into_iter()
+            .map(|info| (info.public_key.clone(), self.get_delegated_amount(info.public_key)))
+            .filter(|(_, amount)| !amount.is_zero())
+            .collect();
+
+        let mut remaining = rewards;
+        for (index, (validator, amount)) in delegated.iter().enumerate() {
+            // The last delegation takes the rounding remainder
+            let share = if index + 1 == delegated.len() {
+                remaining
+            } else {
+                self.unwrap_math(math::mul_div(rewards, *amount, total_delegated, Rounding::Down))
+            };
+            self.record_delegation(validator.clone(), share, true);
+            remaining -= share;
+        }
+    }
+
+    // Takes the protocol fee out of freshly compounded rewards by minting the treasury
+    // stCSPR worth exactly `fee` at the post-reward rate, so other holders keep the rest.
+    fn collect_protocol_fee(&mut self, rewards: U512) {
+        let Some(treasury) = self.treasury.get() else {
+            return;
+        };
+        let fee = rewards * U512::from(self.get_protocol_fee()) / U512::from(10000);
+        let supply = self.u256_to_u512(self.token.total_supply());
+        if fee.is_zero() || supply.is_zero() {
+            return;
+        }
+
+        // Priced against the pre-fee totals, the shares are worth `fee` once minted
+        let total_staked = self.total_staked.get_or_default();
+        let shares = self.unwrap_math(math::to_shares(fee, total_staked - fee, supply, Rounding::Down));
+        self.token.mint(treasury, self.u512_to_u256(shares));
+
+        let collected = self.total_protocol_fees.get_or_default();
+        self.total_protocol_fees.set(collected + fee);
+        self.env().emit_event(ProtocolFeeCollected {
+            treasury,
+            rewards,
+            fee,
+            shares,
+        });
+    }
+
+    fn update_exchange_rate(&mut self) {
+        let total_staked = self.total_staked.get_or_default();
+        let total_supply_u256 = self.token.total_supply();
+        let total_supply = self.u256_to_u512(total_supply_u256);
+
+        if total_supply > U512::zero() {
+            let new_rate = self.unwrap_math(math::exchange_rate(total_staked, total_supply));
+            let previous_rate = self.exchange_rate.get_or_default();
+            if new_rate == previous_rate {
+                return;
+            }
+            self.exchange_rate.set(new_rate);
+            self.env().emit_event(ExchangeRateUpdated {
+                previous_rate,
+                rate: new_rate,
+            });
+        }
+    }
+
+    // One step of `migrate`, from `version` to `version + 1`
+    fn migrate_from(&mut self, version: u32) {
+        match version {
+            // Unversioned storage scaled the exchange rate by 1e9
+            0 => {
+                let previous_rate = self.exchange_rate.get_or_default();
+                let scale = U512::from(RATE_PRECISION / LEGACY_RATE_PRECISION);
+                let rate = self.unwrap_math(previous_rate.checked_mul(scale));
+                self.exchange_rate.set(rate);
+                self.env().emit_event(ExchangeRateUpdated { previous_rate, rate });
+            }
+            _ => self.env().revert(Error::InvalidStorageVersion),
+        }
+    }
+
+    fn unwrap_math(&self, value: Option<U512>) -> U512 {
+        value.unwrap_or_revert_with(&self.env(), Error::ArithmeticOverflow)
+    }
+
+    fn checked_add(&self, a: U512, b: U512) -> U512 {
+        a.checked_add(b).unwrap_or_revert_with(&self.env(), Error::ArithmeticOverflow)
+    }
+
+    // Subtractions on tracked balances; an underflow means the books are inconsistent
+    fn checked_sub(&self, a: U512, b: U512) -> U512 {
+        a.checked_sub(b).unwrap_or_revert_with(&self.env(), Error::AccountingUnderflow)
+    }
+
+    fn u512_to_u256(&self, value: U512) -> U256 {
+        let mut bytes = [0u8; 64];
+        value.to_little_endian(&mut bytes);
+        if bytes[32..].iter().any(|byte| *byte != 0) {
+            self.env().revert(Error::AmountTooLarge);
+        }
+        U256::from_little_endian(&bytes[..32])
+    }
+
+    fn u256_to_u512(&self, value: U256) -> U512 {
+        let mut bytes = [0u8; 32];
+        value.to_little_endian(&mut bytes);
+        U512::from_little_endian(&bytes)
+    }
+}
+
+#[odra::odra_error]
+pub enum Error {
+    AmountTooSmall = 100,
+    InsufficientPoolLiquidity = 101,
+    ValidatorHasDelegations = 103,
+    FeeTooHigh = 104,
+    InvalidFeeCurve = 105,
+    DepositsPaused = 106,
+    InstantWithdrawalsPaused = 107,
+    WithdrawalsPaused = 108,
+    TransfersPaused = 109,
+    DepositCapExceeded = 110,
+    AccountCapExceeded = 111,
+    SlippageExceeded = 112,
+    DeadlineExpired = 113,
+    ArithmeticOverflow = 114,
+    AccountingUnderflow = 115,
+    AmountTooLarge = 116,
+    InsufficientAttachedValue = 117,
+    InvalidStorageVersion = 118,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::staking_pool::{
+        Deposited, Error, ExchangeRateUpdated, InstantWithdrawn, LiquidityAdded, PausableAction, PauseToggled,
+        ProtocolFeeCollected, RewardsCompounded, StakeMoved, StakingPool, StakingPoolHostRef, StakingPoolInitArgs,
+        StorageMigrated, WithdrawalRequested, STORAGE_VERSION,
+    };
+    use crate::access_control::{Error as AccessError, Role, RoleGranted};
+    use crate::fee_curve::FeeCurve;
+    use crate::liquidity::Error as LiquidityError;
+    use crate::math::{RATE_PRECISION, VIRTUAL_OFFSET};
+    use crate::reward_oracle::{Error as OracleError, ERA_DURATION};
+    use crate::token::{Approve, Burn, Error as TokenError, Mint, Transfer};
+    use crate::validator_registry::Error as RegistryError;
+    use crate::withdrawal_queue::{Error as QueueError, WithdrawalStatus};
+    use odra::casper_types::{U256, U512};
+    use odra::host::{Deployer, HostEnv, HostRef, InstallConfig, NoArgs};
+    use odra::prelude::*;
+
+    const UNBONDING_DELAY: u64 = 14 * 60 * 60 * 1000; // 14 hours
+
+    fn cspr(amount: u64) -> U512 {
+        U512::from(amount) * U512::from(1_000_000_000u64)
+    }
+
+    fn setup() -> (HostEnv, StakingPoolHostRef) {
+        let env = odra_test::env();
+        let mut pool = StakingPool::deploy(&env, StakingPoolInitArgs {
+            unbonding_delay: UNBONDING_DELAY,
+        });
+        let admin = env.get_account(0);
+        pool.grant_role(Role::Operator, admin);
+        pool.grant_role(Role::Treasury, admin);
+        pool.grant_role(Role::Pauser, admin);
+        pool.add_validator(env.get_validator(0), 5_000, cspr(1_000_000));
+        pool.add_validator(env.get_validator(1), 5_000, cspr(1_000_000));
+        (env, pool)
+    }
+
+    #[test]
+    fn standard_withdrawal_is_queued_until_unbonding_delay() {
+        let (env, mut pool) = setup();
+        let user = env.get_account(1);
+        env.set_caller(user);
+        pool.with_tokens(cspr(100)).deposit();
+
+        let balance_before = env.balance_of(&user);
+        pool.withdraw(cspr(40), false);
+        assert_eq!(env.balance_of(&user), balance_before);
+        assert_eq!(pool.get_total_staked(), cspr(60));
+        assert_eq!(pool.get_total_pending_withdrawals(), cspr(40));
+
+        let pending = pool.get_pending_withdrawals(user);
+        assert_eq!(pending.len(), 1);
+        let request = &pending[0];
+        assert_eq!(request.owner, user);
+        assert_eq!(request.cspr_amount, cspr(40));
+        assert_eq!(request.claimable_at, request.created_at + UNBONDING_DELAY);
+
+        assert_eq!(
+            pool.try_claim_withdrawal(request.id),
+            Err(QueueError::WithdrawalNotReady.into())
+        );
+
+        env.advance_with_auctions(UNBONDING_DELAY);
+        pool.claim_withdrawal(request.id);
+        assert_eq!(env.balance_of(&user), balance_before + cspr(40));
+        assert!(pool.get_pending_withdrawals(user).is_empty());
+        assert_eq!(pool.get_total_pending_withdrawals(), U512::zero());
+        assert_eq!(
+            pool.get_withdrawal_request(request.id).unwrap().status,
+            WithdrawalStatus::Claimed
+        );
+
+        assert_eq!(
+            pool.try_claim_withdrawal(request.id),
+            Err(QueueError::WithdrawalAlreadyClaimed.into())
+        );
+    }
+
+    #[test]
+    fn only_owner_can_claim_withdrawal() {
+        let (env, mut pool) = setup();
+        let (user, other) = (env.get_account(1), env.get_account(2));
+        env.set_caller(user);
+        pool.with_tokens(cspr(10)).deposit();
+        pool.withdraw(cspr(10), false);
+        let request_id = pool.get_pending_withdrawals(user)[0].id;
+
+        env.advance_with_auctions(UNBONDING_DELAY);
+        env.set_caller(other);
+        assert_eq!(
+            pool.try_claim_withdrawal(request_id),
+            Err(QueueError::NotRequestOwner.into())
+        );
+        assert_eq!(
+            pool.try_claim_withdrawal(request_id + 1),
+            Err(QueueError::WithdrawalNotFound.into())
+        );
+    }
+
+    #[test]
+    fn deposits_are_delegated_and_withdrawals_undelegated() {
+        let (env, mut pool) = setup();
+        let (v0, v1) = (env.get_validator(0), env.get_validator(1));
+        env.set_caller(env.get_account(1));
+
+        pool.with_tokens(cspr(1_000)).deposit();
+        pool.with_tokens(cspr(600)).deposit();
+        assert_eq!(pool.get_delegated_amount(v0.clone()), cspr(800));
+        assert_eq!(pool.get_delegated_amount(v1.clone()), cspr(800));
+        assert_eq!(pool.get_total_delegated(), cspr(1_600));
+
+        // Largest delegations are unwound first. The 400 CSPR left on v1 would be
+        // below the minimum delegation, so v1 is undelegated whole and the excess
+        // unbonds back into idle stake
+        pool.withdraw(cspr(1_200), false);
+        assert_eq!(pool.get_delegated_amount(v0.clone()), U512::zero());
+        assert_eq!(pool.get_delegated_amount(v1.clone()), U512::zero());
+        assert_eq!(pool.get_total_redelegating(), cspr(400));
+
+        env.advance_with_auctions(UNBONDING_DELAY);
+        env.set_caller(env.get_account(0));
+        pool.rebalance(1);
+        assert_eq!(pool.get_total_redelegating(), U512::zero());
+        assert_eq!(pool.get_total_delegated(), U512::zero());
+
+        // With the idle 400 CSPR, 1,500 CSPR goes to each validator. Withdrawing
+        // 1,200 CSPR keeps the minimum on v0 and takes the rest from v1
+        pool.with_tokens(cspr(2_600)).deposit();
+        pool.withdraw(cspr(1_200), false);
+        assert_eq!(pool.get_delegated_amount(v0), cspr(500));
+        assert_eq!(pool.get_delegated_amount(v1), cspr(1_300));
+        assert_eq!(pool.get_total_redelegating(), U512::zero());
+    }
+
+    #[test]
+    fn deposits_below_minimum_delegation_stay_idle() {
+        let (env, mut pool) = setup();
+        let (v0, v1) = (env.get_validator(0), env.get_validator(1));
+        env.set_caller(env.get_account(1));
+
+        // Neither half of 100 CSPR reaches Casper's 500 CSPR minimum delegation
+        pool.with_tokens(cspr(100)).deposit();
+        pool.with_tokens(cspr(10)).deposit();
+        assert_eq!(pool.get_total_delegated(), U512::zero());
+        assert_eq!(env.balance_of(pool.address()), cspr(110));
+
+        // Once idle stake covers the minimum for both validators it is delegated
+        pool.with_tokens(cspr(890)).deposit();
+        assert_eq!(pool.get_delegated_amount(v0), cspr(500));
+        assert_eq!(pool.get_delegated_amount(v1), cspr(500));
+        assert_eq!(env.balance_of(pool.address()), U512::zero());
+    }
+
+    #[test]
+    fn admin_manages_validator_registry() {
+        let (env, mut pool) = setup();
+        let (v0, v1, v2) = (env.get_validator(0), env.get_validator(1), env.get_validator(2));
+
+        assert_eq!(pool.get_validators().len(), 2);
+        assert_eq!(
+            pool.try_add_validator(v2.clone(), 1, cspr(100)),
+            Err(RegistryError::WeightsExceedTotal.into())
+        );
+        assert_eq!(
+            pool.try_add_validator(v0.clone(), 0, cspr(100)),
+            Err(RegistryError::ValidatorAlreadyRegistered.into())
+        );
+
+        pool.set_validator_weight(v1.clone(), 3_000);
+        pool.add_validator(v2.clone(), 2_000, cspr(100));
+        pool.disable_validator(v0.clone());
+        pool.set_validator_cap(v2.clone(), cspr(200));
+
+        let info = pool.get_validator(v0.clone()).unwrap();
+        assert!(!info.enabled);
+        assert_eq!(pool.get_validator(v1.clone()).unwrap().weight_bp, 3_000);
+        assert_eq!(pool.get_validator(v2.clone()).unwrap().cap, cspr(200));
+
+        pool.remove_validator(v2.clone());
+        assert_eq!(pool.get_validator(v2.clone()), None);
+        assert_eq!(
+            pool.try_disable_validator(v2),
+            Err(RegistryError::ValidatorNotFound.into())
+        );
+
+        env.set_caller(env.get_account(1));
+        assert_eq!(pool.try_enable_validator(v0), Err(AccessError::MissingRole.into()));
+    }
+
+    #[test]
+    fn validator_with_delegations_cannot_be_removed() {
+        let (env, mut pool) = setup();
+        let v0 = env.get_validator(0);
+        env.set_caller(env.get_account(1));
+        pool.with_tokens(cspr(1_000)).deposit();
+
+        env.set_caller(env.get_account(0));
+        assert_eq!(
+            pool.try_remove_validator(v0),
+            Err(Error::ValidatorHasDelegations.into())
+        );
+    }
+
+    #[test]
+    fn routing_fills_most_under_allocated_validator_first() {
+        let (env, mut pool) = setup();
+        let (v0, v1, v2) = (env.get_validator(0), env.get_validator(1), env.get_validator(2));
+        pool.set_validator_weight(v0.clone(), 2_000);
+        pool.set_validator_weight(v1.clone(), 3_000);
+        pool.add_validator(v2.clone(), 5_000, cspr(1_500));
+
+        // 10k CSPR targets 2k / 3k / 5k, but v2 is capped at 1.5k. The 3.5k it cannot
+        // take is split 2:3 between v0 and v1
+        let routing = pool.preview_routing(cspr(10_000));
+        assert_eq!(routing.len(), 3);
+        assert_eq!((routing[0].validator.clone(), routing[0].amount), (v2.clone(), cspr(1_500)));
+        assert_eq!((routing[1].validator.clone(), routing[1].amount), (v1.clone(), cspr(5_100)));
+        assert_eq!((routing[2].validator.clone(), routing[2].amount), (v0.clone(), cspr(3_400)));
+
+        // Amounts below the minimum delegation stay in the purse until they add up
+        env.set_caller(env.get_account(1));
+        pool.with_tokens(cspr(400)).deposit();
+        assert_eq!(pool.get_total_delegated(), U512::zero());
+        assert!(pool.preview_routing(U512::zero()).is_empty());
+
+        // Neither a target share nor a weighted share reaches the minimum, so the
+        // most under-allocated validator takes it whole
+        pool.with_tokens(cspr(400)).deposit();
+        assert_eq!(pool.get_total_delegated(), cspr(800));
+        assert_eq!(pool.get_delegated_amount(v2), cspr(800));
+        assert_eq!(pool.get_delegated_amount(v1), U512::zero());
+        assert_eq!(pool.get_delegated_amount(v0), U512::zero());
+    }
+
+    #[test]
+    fn rebalance_moves_stake_toward_new_weights() {
+        let (env, mut pool) = setup();
+        let (v0, v1) = (env.get_validator(0), env.get_validator(1));
+        env.set_caller(env.get_account(1));
+        pool.with_tokens(cspr(2_000)).deposit();
+        assert_eq!(pool.get_delegated_amount(v0.clone()), cspr(1_000));
+
+        env.set_caller(env.get_account(0));
+        pool.set_validator_weight(v1.clone(), 2_000);
+        pool.set_validator_weight(v0.clone(), 8_000);
+
+        pool.rebalance(0);
+        assert_eq!(pool.get_delegated_amount(v1.clone()), cspr(1_000));
+
+        // The 600 CSPR surplus would leave v1 below the minimum delegation, so v1
+        // keeps 500 CSPR
+        pool.rebalance(5);
+        assert!(env.emitted_event(&pool, StakeMoved {
+            from: v1.clone(),
+            to: v0.clone(),
+            amount: cspr(500),
+        }));
+        assert_eq!(pool.get_delegated_amount(v1.clone()), cspr(500));
+        assert_eq!(pool.get_total_redelegating(), cspr(500));
+        assert!(pool.preview_rebalance(5).is_empty());
+
+        // Nothing to redelegate until the moved stake has unbonded
+        pool.rebalance(5);
+        assert_eq!(pool.get_delegated_amount(v0.clone()), cspr(1_000));
+
+        env.advance_with_auctions(UNBONDING_DELAY);
+        pool.rebalance(5);
+        assert_eq!(pool.get_delegated_amount(v0), cspr(1_500));
+        assert_eq!(pool.get_total_redelegating(), U512::zero());
+        assert_eq!(pool.get_total_delegated(), cspr(2_000));
+    }
+
+    #[test]
+    fn disabled_validator_is_drained_in_bounded_batches() {
+        let (env, mut pool) = setup();
+        let (v0, v1, v2) = (env.get_validator(0), env.get_validator(1), env.get_validator(2));
+        pool.set_validator_weight(v0.clone(), 3_000);
+        pool.set_validator_weight(v1.clone(), 3_000);
+        pool.add_validator(v2.clone(), 4_000, cspr(1_000_000));
+        env.set_caller(env.get_account(1));
+        pool.with_tokens(cspr(10_000)).deposit();
+
+        env.set_caller(env.get_account(0));
+        pool.disable_validator(v2.clone());
+        assert_eq!(pool.preview_rebalance(5).len(), 2);
+
+        pool.rebalance(1);
+        assert_eq!(pool.get_delegated_amount(v2.clone()), cspr(2_000));
+        pool.rebalance(1);
+        assert_eq!(pool.get_delegated_amount(v2), U512::zero());
+    }
+
+    #[test]
+    fn sync_rewards_measures_delegation_balances() {
+        let (env, mut pool) = setup();
+        let (v0, v1) = (env.get_validator(0), env.get_validator(1));
+        env.set_caller(env.get_account(1));
+        pool.with_tokens(cspr(2_000)).deposit();
+        pool.with_tokens(cspr(100)).deposit();
+
+        env.advance_with_auctions(UNBONDING_DELAY);
+        let balance = pool.get_delegation_balance(v0.clone()) + pool.get_delegation_balance(v1.clone());
+        let rewards = balance.saturating_sub(cspr(2_100));
+
+        pool.sync_rewards();
+        assert_eq!(pool.get_total_delegated(), cspr(2_100) + rewards);
+        assert_eq!(pool.get_delegated_amount(v0.clone()), pool.get_delegation_balance(v0));
+        assert_eq!(pool.get_total_staked(), cspr(2_100) + rewards);
+        let offset = U512::from(VIRTUAL_OFFSET);
+        assert_eq!(
+            pool.get_exchange_rate(),
+            (cspr(2_100) + rewards + offset) * U512::from(RATE_PRECISION) / (cspr(2_100) + offset)
+        );
+    }
+
+    #[test]
+    fn reported_rewards_are_bounded_and_update_exchange_rate() {
+        let (env, mut pool) = setup();
+        let (reporter, user) = (env.get_account(2), env.get_account(1));
+        pool.grant_role(Role::Reporter, reporter);
+        pool.set_reward_start_era(99);
+        env.set_caller(user);
+        pool.with_tokens(cspr(10_000)).deposit();
+
+        assert_eq!(
+            pool.try_report_rewards(100, cspr(1)),
+            Err(AccessError::MissingRole.into())
+        );
+
+        // 20% APR allows ~0.456 CSPR per era on 10k CSPR
+        env.set_caller(reporter);
+        assert_eq!(
+            pool.try_report_rewards(100, cspr(1)),
+            Err(OracleError::RewardTooHigh.into())
+        );
+        pool.report_rewards(100, U512::from(400_000_000u64));
+        assert_eq!(pool.get_last_reported_era(), 100);
+        assert_eq!(pool.get_exchange_rate(), U512::from(1_000_039_996_000_399_960u64));
+        assert_eq!(pool.try_report_rewards(100, U512::one()), Err(OracleError::StaleEra.into()));
+
+        // A longer gap between reports allows proportionally more
+        env.advance_block_time(10 * ERA_DURATION);
+        pool.report_rewards(110, cspr(1));
+        assert_eq!(pool.get_last_reported_era(), 110);
+        assert_eq!(pool.get_total_staked(), U512::from(10_001_400_000_000u64));
+    }
+
+    #[test]
+    fn reported_eras_cannot_run_ahead_of_time() {
+        let (env, mut pool) = setup();
+        let reporter = env.get_account(2);
+        pool.grant_role(Role::Reporter, reporter);
+        pool.with_tokens(cspr(10_000)).deposit();
+
+        // The first report is bounded too, so it cannot lock out later reports
+        env.set_caller(reporter);
+        assert_eq!(
+            pool.try_report_rewards(u64::MAX - 1, U512::zero()),
+            Err(OracleError::FutureEra.into())
+        );
+        assert_eq!(pool.try_report_rewards(2, U512::zero()), Err(OracleError::FutureEra.into()));
+
+        // Until then the admin can anchor reports at the current chain era
+        env.set_caller(env.get_account(0));
+        pool.set_reward_start_era(99);
+        env.set_caller(reporter);
+        assert_eq!(pool.try_report_rewards(99, U512::zero()), Err(OracleError::StaleEra.into()));
+        pool.report_rewards(100, U512::zero());
+
+        // A far-future era would both widen the APR bound and lock out later reports
+        assert_eq!(
+            pool.try_report_rewards(u64::MAX, cspr(1_000)),
+            Err(OracleError::FutureEra.into())
+        );
+        assert_eq!(pool.try_report_rewards(101, U512::zero()), Err(OracleError::FutureEra.into()));
+
+        env.advance_block_time(2 * ERA_DURATION);
+        assert_eq!(pool.try_report_rewards(103, U512::zero()), Err(OracleError::FutureEra.into()));
+        pool.report_rewards(102, U512::from(900_000_000u64));
+        assert_eq!(pool.get_last_reported_era(), 102);
+
+        env.set_caller(env.get_account(0));
+        assert_eq!(
+            pool.try_set_reward_start_era(200),
+            Err(OracleError::ReportingStarted.into())
+        );
+    }
+
+    #[test]
+    fn reported_rewards_are_undelegated_on_withdrawal() {
+        let (env, mut pool) = setup();
+        let (admin, user) = (env.get_account(0), env.get_account(1));
+        let (v0, v1) = (env.get_validator(0), env.get_validator(1));
+        pool.grant_role(Role::Reporter, admin);
+        pool.with_tokens(cspr(500)).add_instant_liquidity();
+        env.set_caller(user);
+        pool.with_tokens(cspr(1_000)).deposit();
+
+        // Report part of what the delegations earned, within one era's APR bound
+        env.advance_with_auctions(UNBONDING_DELAY);
+        let balance = pool.get_delegation_balance(v0.clone()) + pool.get_delegation_balance(v1.clone());
+        let rewards = (balance - cspr(1_000)).min(U512::from(40_000_000u64));
+        env.set_caller(admin);
+        pool.report_rewards(1, rewards);
+        assert_eq!(pool.get_total_delegated(), cspr(1_000) + rewards);
+        assert_eq!(pool.get_delegated_amount(v0) + pool.get_delegated_amount(v1), cspr(1_000) + rewards);
+
+        // Withdrawing everything undelegates the rewards along with the deposit
+        env.set_caller(user);
+        pool.withdraw(U512::from(pool.get_stcspr_balance(user).as_u128()), false);
+        assert_eq!(pool.get_total_delegated(), pool.get_total_staked());
+        let request = pool.get_pending_withdrawals(user)[0].clone();
+
+        // The claim is paid from unbonded stake, leaving the instant pool's liquidity alone
+        env.advance_with_auctions(UNBONDING_DELAY);
+        let balance = env.balance_of(&user);
+        pool.claim_withdrawal(request.id);
+        assert_eq!(env.balance_of(&user), balance + request.cspr_amount);
+        assert_eq!(pool.get_instant_pool_balance(), cspr(500));
+        assert!(env.balance_of(pool.address()) >= cspr(500));
+    }
+
+    #[test]
+    fn reported_rewards_require_quorum() {
+        let (env, mut pool) = setup();
+        let (r1, r2, r3) = (env.get_account(2), env.get_account(3), env.get_account(4));
+        pool.grant_role(Role::Reporter, r1);
+        pool.grant_role(Role::Reporter, r2);
+        pool.grant_role(Role::Reporter, r3);
+        pool.set_reporter_quorum(2);
+        pool.set_reward_start_era(99);
+        env.set_caller(env.get_account(1));
+        pool.with_tokens(cspr(10_000)).deposit();
+
+        let amount = U512::from(300_000_000u64);
+        env.set_caller(r1);
+        pool.report_rewards(100, amount);
+        assert_eq!(pool.try_report_rewards(100, amount), Err(OracleError::AlreadyReported.into()));
+
+        // Disagreeing reporter does not complete the quorum
+        env.set_caller(r2);
+        pool.report_rewards(100, U512::from(200_000_000u64));
+        assert_eq!(pool.get_last_reported_era(), 99);
+        assert_eq!(pool.get_total_staked(), cspr(10_000));
+
+        env.set_caller(r3);
+        pool.report_rewards(100, amount);
+        assert_eq!(pool.get_last_reported_era(), 100);
+        assert_eq!(pool.get_total_staked(), cspr(10_000) + amount);
+    }
+
+    #[test]
+    fn protocol_fee_is_minted_to_treasury() {
+        let (env, mut pool) = setup();
+        let (user, reporter, treasury) = (env.get_account(1), env.get_account(2), env.get_account(3));
+        pool.grant_role(Role::Reporter, reporter);
+        pool.set_treasury(treasury);
+        assert_eq!(pool.try_set_protocol_fee(2_001), Err(Error::FeeTooHigh.into()));
+        pool.set_protocol_fee(1_000);
+        pool.set_reward_start_era(99);
+
+        env.set_caller(user);
+        assert_eq!(pool.try_set_protocol_fee(0), Err(AccessError::MissingRole.into()));
+        pool.with_tokens(cspr(10_000)).deposit();
+
+        // 10% of 0.4 CSPR rewards goes to the treasury
+        env.set_caller(reporter);
+        pool.report_rewards(100, U512::from(400_000_000u64));
+        assert_eq!(pool.get_total_protocol_fees(), U512::from(40_000_000u64));
+        assert_eq!(pool.get_stcspr_balance(treasury), U256::from(39_998_560u64));
+        assert_eq!(pool.get_exchange_rate(), U512::from(1_000_035_996_400_379_544u64));
+        assert!(env.emitted_event(&pool, ProtocolFeeCollected {
+            treasury,
+            rewards: U512::from(400_000_000u64),
+            fee: U512::from(40_000_000u64),
+            shares: U512::from(39_998_560u64),
+        }));
+    }
+
+    #[test]
+    fn protocol_fee_shares_are_worth_the_fee_in_small_pools() {
+        let (env, mut pool) = setup();
+        let treasury = env.get_account(3);
+        pool.set_treasury(treasury);
+        pool.set_protocol_fee(1_000);
+        pool.with_tokens(cspr(1)).deposit();
+
+        // 1 CSPR of rewards on a 1 CSPR pool, where the virtual offset weighs the most
+        let mut donor = Donor::deploy(&env, NoArgs);
+        donor.with_tokens(cspr(1)).donate(*pool.address());
+        pool.sync_rewards();
+
+        let fee = U512::from(100_000_000u64);
+        assert_eq!(pool.get_total_protocol_fees(), fee);
+        let shares = U512::from(pool.get_stcspr_balance(treasury).as_u128());
+        assert_eq!(shares, U512::from(68_965_517u64));
+        assert_eq!(pool.convert_to_cspr(shares), fee - U512::one());
+    }
+
+    #[test]
+    fn liquidity_providers_receive_redeemable_shares() {
+        let (env, mut pool) = setup();
+        let (alice, bob) = (env.get_account(1), env.get_account(2));
+
+        env.set_caller(alice);
+        pool.with_tokens(cspr(1_000)).add_instant_liquidity();
+        env.set_caller(bob);
+        pool.with_tokens(cspr(500)).add_instant_liquidity();
+        assert_eq!(pool.get_lp_shares(alice), cspr(1_000));
+        assert_eq!(pool.get_lp_shares(bob), cspr(500));
+        assert_eq!(pool.get_total_lp_shares(), cspr(1_500));
+
+        assert_eq!(
+            pool.try_remove_instant_liquidity(cspr(501)),
+            Err(LiquidityError::InsufficientShares.into())
+        );
+
+        let balance_before = env.balance_of(&bob);
+        pool.remove_instant_liquidity(cspr(200));
+        assert_eq!(env.balance_of(&bob), balance_before + cspr(200));
+        assert_eq!(pool.get_lp_shares(bob), cspr(300));
+        assert_eq!(pool.get_instant_pool_balance(), cspr(1_300));
+    }
+
+    #[test]
+    fn instant_unstake_fee_accrues_to_lps_and_treasury() {
+        let (env, mut pool) = setup();
+        let (lp, user, treasury) = (env.get_account(1), env.get_account(2), env.get_account(3));
+        pool.set_treasury(treasury);
+        pool.set_instant_fee_treasury_share(2_000);
+
+        env.set_caller(lp);
+        pool.with_tokens(cspr(1_000)).add_instant_liquidity();
+        env.set_caller(user);
+        pool.with_tokens(cspr(1_000)).deposit();
+
+        let user_before = env.balance_of(&user);
+        let treasury_before = env.balance_of(&treasury);
+        pool.withdraw(cspr(100), true);
+
+        // 0.5 CSPR fee: 0.1 to the treasury, 0.4 stays with LPs
+        let milli = |amount: u64| U512::from(amount) * U512::from(1_000_000u64);
+        assert_eq!(env.balance_of(&user), user_before + milli(99_500));
+        assert_eq!(env.balance_of(&treasury), treasury_before + milli(100));
+        assert_eq!(pool.get_total_instant_treasury_fees(), milli(100));
+        assert_eq!(pool.get_total_protocol_fees(), U512::zero());
+        assert_eq!(pool.get_instant_pool_balance(), milli(900_400));
+        assert_eq!(pool.get_instant_receivable(), cspr(100));
+        assert_eq!(pool.get_instant_pool_value(), milli(1_000_400));
+        assert_eq!(pool.get_total_staked(), cspr(900));
+    }
+
+    #[test]
+    fn instant_fee_rises_with_pool_utilization() {
+        let (env, mut pool) = setup();
+        let (lp, user) = (env.get_account(1), env.get_account(2));
+        env.set_caller(lp);
+        pool.with_tokens(cspr(1_000)).add_instant_liquidity();
+        env.set_caller(user);
+        pool.with_tokens(cspr(2_000)).deposit();
+
+        let quote = pool.quote_instant_withdraw(cspr(100));
+        assert_eq!(quote.fee_bp, 50);
+
+        // 90% utilization is halfway between the kink and 100%
+        let quote = pool.quote_instant_withdraw(cspr(900));
+        assert_eq!(quote.fee_bp, 275);
+        assert_eq!(quote.fee, U512::from(24_750_000_000u64));
+        assert_eq!(quote.net_amount, U512::from(875_250_000_000u64));
+
+        let balance_before = env.balance_of(&user);
+        pool.withdraw(cspr(900), true);
+        assert_eq!(env.balance_of(&user), balance_before + quote.net_amount);
+    }
+
+    #[test]
+    fn admin_configures_instant_fee_curve() {
+        let (env, mut pool) = setup();
+        let curve = FeeCurve {
+            base_fee_bp: 10,
+            kink_utilization_bp: 5_000,
+            kink_fee_bp: 110,
+            max_fee_bp: 610,
+        };
+        pool.set_instant_fee_curve(curve.clone());
+        assert_eq!(pool.get_instant_fee_curve(), curve);
+
+        let invalid = FeeCurve { max_fee_bp: 100, ..curve.clone() };
+        assert_eq!(pool.try_set_instant_fee_curve(invalid), Err(Error::InvalidFeeCurve.into()));
+
+        env.set_caller(env.get_account(1));
+        assert_eq!(pool.try_set_instant_fee_curve(curve), Err(AccessError::MissingRole.into()));
+    }
+
+    #[test]
+    fn instant_pool_is_refilled_from_matured_unbonding() {
+        let (env, mut pool) = setup();
+        let (lp, user) = (env.get_account(1), env.get_account(2));
+        env.set_caller(lp);
+        pool.with_tokens(cspr(1_000)).add_instant_liquidity();
+        env.set_caller(user);
+        pool.with_tokens(cspr(2_000)).deposit();
+
+        pool.withdraw(cspr(100), true);
+        let milli = |amount: u64| U512::from(amount) * U512::from(1_000_000u64);
+        assert_eq!(pool.get_total_delegated(), cspr(1_900));
+        let requests = pool.get_pending_receivables(10);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].cspr_amount, cspr(100));
+
+        pool.settle_instant_pool(10);
+        assert_eq!(pool.get_instant_receivable(), cspr(100));
+
+        env.advance_with_auctions(UNBONDING_DELAY);
+        pool.settle_instant_pool(10);
+        assert_eq!(pool.get_instant_receivable(), U512::zero());
+        assert_eq!(pool.get_instant_pool_balance(), milli(1_000_500));
+        assert_eq!(pool.get_instant_pool_value(), milli(1_000_500));
+        assert!(pool.get_pending_receivables(10).is_empty());
+
+        // Pool-owned requests cannot be claimed by users
+        assert_eq!(
+            pool.try_claim_withdrawal(requests[0].id),
+            Err(QueueError::NotRequestOwner.into())
+        );
+    }
+
+    #[test]
+    fn instant_pool_settles_in_bounded_batches() {
+        let (env, mut pool) = setup();
+        pool.with_tokens(cspr(1_000)).add_instant_liquidity();
+        env.set_caller(env.get_account(1));
+        pool.with_tokens(cspr(2_000)).deposit();
+        for _ in 0..3 {
+            pool.withdraw(cspr(10), true);
+        }
+        assert_eq!(pool.get_instant_receivable(), cspr(30));
+
+        env.advance_with_auctions(UNBONDING_DELAY);
+        pool.settle_instant_pool(2);
+        assert_eq!(pool.get_instant_receivable(), cspr(10));
+        assert_eq!(pool.get_pending_receivables(10).len(), 1);
+
+        pool.settle_instant_pool(2);
+        assert_eq!(pool.get_instant_receivable(), U512::zero());
+        assert!(pool.get_pending_receivables(10).is_empty());
+    }
+
+    #[test]
+    fn instant_withdrawal_queues_what_the_pool_cannot_cover() {
+        let (env, mut pool) = setup();
+        let (lp, user) = (env.get_account(1), env.get_account(2));
+        env.set_caller(lp);
+        pool.with_tokens(cspr(100)).add_instant_liquidity();
+        env.set_caller(user);
+        pool.with_tokens(cspr(1_000)).deposit();
+
+        assert_eq!(
+            pool.try_withdraw(cspr(300), true),
+            Err(Error::InsufficientPoolLiquidity.into())
+        );
+
+        // Draining the pool entirely is charged the maximum 5% fee
+        let balance_before = env.balance_of(&user);
+        let summary = pool.withdraw_instant_or_queue(cspr(300));
+        assert_eq!(summary.paid, cspr(95));
+        assert_eq!(summary.fee, cspr(5));
+        assert_eq!(summary.queued, cspr(200));
+        assert_eq!(env.balance_of(&user), balance_before + cspr(95));
+        assert_eq!(pool.get_total_staked(), cspr(700));
+
+        let request = pool.get_withdrawal_request(summary.request_id.unwrap()).unwrap();
+        assert_eq!(request.owner, user);
+        assert_eq!(request.cspr_amount, cspr(200));
+
+        // Only the 5 CSPR fee left in the pool can be paid out now
+        let summary = pool.withdraw_instant_or_queue(cspr(50));
+        assert_eq!(summary.paid, U512::from(4_750_000_000u64));
+        assert_eq!(summary.queued, cspr(45));
+        assert_eq!(pool.get_instant_pool_balance(), U512::from(250_000_000u64));
+        assert_eq!(pool.get_pending_withdrawals(user).len(), 2);
+    }
+
+    #[test]
+    fn roles_are_granted_revoked_and_renounced() {
+        let (env, mut pool) = setup();
+        let (admin, operator) = (env.get_account(0), env.get_account(1));
+        assert!(pool.has_role(Role::DefaultAdmin, admin));
+        assert!(!pool.has_role(Role::Operator, operator));
+
+        env.set_caller(operator);
+        assert_eq!(pool.try_rebalance(1), Err(AccessError::MissingRole.into()));
+        assert_eq!(
+            pool.try_grant_role(Role::Operator, operator),
+            Err(AccessError::MissingRole.into())
+        );
+
+        env.set_caller(admin);
+        pool.grant_role(Role::Operator, operator);
+        assert!(env.emitted_event(&pool, RoleGranted {
+            role: Role::Operator,
+            account: operator,
+            sender: admin,
+        }));
+        env.set_caller(operator);
+        pool.rebalance(1);
+
+        pool.renounce_role(Role::Operator);
+        assert!(!pool.has_role(Role::Operator, operator));
+
+        env.set_caller(admin);
+        pool.grant_role(Role::Operator, operator);
+        pool.revoke_role(Role::Operator, operator);
+        assert!(!pool.has_role(Role::Operator, operator));
+    }
+
+    #[test]
+    fn paused_actions_are_blocked_until_unpaused() {
+        let (env, mut pool) = setup();
+        let (admin, user, other) = (env.get_account(0), env.get_account(1), env.get_account(2));
+        pool.with_tokens(cspr(1_000)).add_instant_liquidity();
+        env.set_caller(user);
+        pool.with_tokens(cspr(100)).deposit();
+
+        assert_eq!(pool.try_pause(PausableAction::Deposits), Err(AccessError::MissingRole.into()));
+
+        env.set_caller(admin);
+        for action in [
+            PausableAction::Deposits,
+            PausableAction::InstantWithdrawals,
+            PausableAction::Withdrawals,
+            PausableAction::Transfers,
+        ] {
+            pool.pause(action.clone());
+            assert!(pool.is_paused(action.clone()));
+            assert!(env.emitted_event(&pool, PauseToggled {
+                action,
+                paused: true,
+                sender: admin,
+            }));
+        }
+
+        env.set_caller(user);
+        assert_eq!(pool.with_tokens(cspr(10)).try_deposit(), Err(Error::DepositsPaused.into()));
+        assert_eq!(pool.try_withdraw(cspr(10), true), Err(Error::InstantWithdrawalsPaused.into()));
+        assert_eq!(pool.try_withdraw(cspr(10), false), Err(Error::WithdrawalsPaused.into()));
+        assert_eq!(
+            pool.try_withdraw_instant_or_queue(cspr(10)),
+            Err(Error::InstantWithdrawalsPaused.into())
+        );
+        assert_eq!(pool.try_transfer(other, U256::from(1)), Err(Error::TransfersPaused.into()));
+        pool.approve(other, U256::from(1));
+        env.set_caller(other);
+        assert_eq!(
+            pool.try_transfer_from(user, other, U256::from(1)),
+            Err(Error::TransfersPaused.into())
+        );
+
+        env.set_caller(admin);
+        pool.unpause(PausableAction::Deposits);
+        pool.unpause(PausableAction::Transfers);
+        assert!(env.emitted_event(&pool, PauseToggled {
+            action: PausableAction::Transfers,
+            paused: false,
+            sender: admin,
+        }));
+
+        env.set_caller(user);
+        pool.with_tokens(cspr(10)).deposit();
+        pool.transfer(other, U256::from(1));
+        assert_eq!(pool.get_stcspr_balance(other), U256::from(1));
+        assert_eq!(pool.try_withdraw(cspr(10), false), Err(Error::WithdrawalsPaused.into()));
+    }
+
+    #[test]
+    fn deposits_respect_global_and_account_caps() {
+        let (env, mut pool) = setup();
+        let (admin, alice, bob) = (env.get_account(0), env.get_account(1), env.get_account(2));
+        assert_eq!(pool.get_remaining_deposit_capacity(), None);
+
+        pool.set_deposit_cap(Some(cspr(1_000)));
+        pool.set_account_deposit_cap(Some(cspr(600)));
+
+        env.set_caller(alice);
+        assert_eq!(pool.try_set_deposit_cap(None), Err(AccessError::MissingRole.into()));
+        pool.with_tokens(cspr(500)).deposit();
+        assert_eq!(pool.get_remaining_account_capacity(alice), Some(cspr(100)));
+        assert_eq!(
+            pool.with_tokens(cspr(101)).try_deposit(),
+            Err(Error::AccountCapExceeded.into())
+        );
+
+        // Withdrawing frees up the account's allowance again
+        pool.withdraw(cspr(200), false);
+        assert_eq!(pool.get_account_deposits(alice), cspr(300));
+
+        env.set_caller(bob);
+        pool.with_tokens(cspr(600)).deposit();
+        assert_eq!(pool.get_remaining_deposit_capacity(), Some(cspr(100)));
+        assert_eq!(pool.get_remaining_account_capacity(alice), Some(cspr(100)));
+
+        env.set_caller(alice);
+        assert_eq!(
+            pool.with_tokens(cspr(150)).try_deposit(),
+            Err(Error::DepositCapExceeded.into())
+        );
+
+        env.set_caller(admin);
+        pool.set_deposit_cap(Some(cspr(2_000)));
+        env.set_caller(alice);
+        pool.with_tokens(cspr(150)).deposit();
+        assert_eq!(pool.get_remaining_account_capacity(alice), Some(cspr(150)));
+    }
+
+    #[test]
+    fn state_changes_emit_events() {
+        let (env, mut pool) = setup();
+        let (admin, user, other) = (env.get_account(0), env.get_account(1), env.get_account(2));
+        let stcspr = |amount: u64| U256::from(amount) * U256::from(1_000_000_000u64);
+
+        pool.with_tokens(cspr(1_000)).add_instant_liquidity();
+        assert!(env.emitted_event(&pool, LiquidityAdded {
+            provider: admin,
+            amount: cspr(1_000),
+            shares: cspr(1_000),
+        }));
+
+        env.set_caller(user);
+        pool.with_tokens(cspr(1_000)).deposit();
+        assert!(env.emitted_event(&pool, Deposited {
+            account: user,
+            cspr_amount: cspr(1_000),
+            stcspr_amount: stcspr(1_000),
+        }));
+        assert!(env.emitted_event(&pool, Mint {
+            recipient: user,
+            amount: stcspr(1_000),
+        }));
+
+        pool.transfer(other, stcspr(100));
+        assert!(env.emitted_event(&pool, Transfer {
+            sender: user,
+            recipient: other,
+            amount: stcspr(100),
+        }));
+        pool.approve(other, stcspr(50));
+        assert!(env.emitted_event(&pool, Approve {
+            owner: user,
+            spender: other,
+            amount: stcspr(50),
+        }));
+
+        pool.withdraw(cspr(100), false);
+        assert!(env.emitted_event(&pool, Burn {
+            owner: user,
+            amount: stcspr(100),
+        }));
+        assert!(env.emitted_event(&pool, WithdrawalRequested {
+            owner: user,
+            request_id: 0,
+            cspr_amount: cspr(100),
+        }));
+
+        let quote = pool.quote_instant_withdraw(cspr(100));
+        pool.withdraw(cspr(100), true);
+        assert!(env.emitted_event(&pool, InstantWithdrawn {
+            account: user,
+            cspr_amount: cspr(100),
+            fee: quote.fee,
+            net_amount: quote.net_amount,
+        }));
+
+        env.set_caller(admin);
+        pool.grant_role(Role::Reporter, admin);
+        pool.report_rewards(1, U512::from(8_000_000u64));
+        assert!(env.emitted_event(&pool, RewardsCompounded {
+            rewards: U512::from(8_000_000u64),
+            total_staked: U512::from(800_008_000_000u64),
+        }));
+        assert!(env.emitted_event(&pool, ExchangeRateUpdated {
+            previous_rate: U512::from(RATE_PRECISION),
+            rate: U512::from(1_000_009_987_515_605_493u64),
+        }));
+    }
+
+    #[test]
+    fn slippage_and_deadline_guards() {
+        let (env, mut pool) = setup();
+        let user = env.get_account(1);
+        pool.with_tokens(cspr(1_000)).add_instant_liquidity();
+        env.set_caller(user);
+
+        let stcspr = |amount: u64| U256::from(amount) * U256::from(1_000_000_000u64);
+        assert_eq!(
+            pool.with_tokens(cspr(100)).try_deposit_with_min(stcspr(101), None),
+            Err(Error::SlippageExceeded.into())
+        );
+        pool.with_tokens(cspr(100)).deposit_with_min(stcspr(100), None);
+        assert_eq!(pool.get_stcspr_balance(user), stcspr(100));
+
+        let deadline = env.block_time() + 1_000;
+        env.advance_block_time(2_000);
+        assert_eq!(
+            pool.with_tokens(cspr(100)).try_deposit_with_min(U256::zero(), Some(deadline)),
+            Err(Error::DeadlineExpired.into())
+        );
+        assert_eq!(
+            pool.try_withdraw_with_min(cspr(10), false, U512::zero(), Some(deadline)),
+            Err(Error::DeadlineExpired.into())
+        );
+
+        // The instant fee counts against the minimum
+        let quote = pool.quote_instant_withdraw(cspr(10));
+        assert_eq!(
+            pool.try_withdraw_with_min(cspr(10), true, cspr(10), None),
+            Err(Error::SlippageExceeded.into())
+        );
+        pool.withdraw_with_min(cspr(10), true, quote.net_amount, None);
+        pool.withdraw_with_min(cspr(10), false, cspr(10), Some(env.block_time()));
+        assert_eq!(pool.get_stcspr_balance(user), stcspr(80));
+    }
+
+    #[test]
+    fn oversized_amounts_revert_instead_of_trapping() {
+        let (env, mut pool) = setup();
+        let (user, reporter) = (env.get_account(1), env.get_account(2));
+        pool.grant_role(Role::Reporter, reporter);
+        env.set_caller(user);
+        pool.with_tokens(cspr(1_000)).deposit();
+
+        // More than a U256 of stCSPR used to be silently truncated
+        let too_large = U512::from(U256::MAX) + U512::one();
+        assert_eq!(pool.try_withdraw(too_large, false), Err(Error::AmountTooLarge.into()));
+        assert_eq!(
+            pool.try_withdraw_instant_or_queue(too_large),
+            Err(Error::AmountTooLarge.into())
+        );
+
+        // Above 1:1 the CSPR value of U512::MAX stCSPR no longer fits
+        env.set_caller(reporter);
+        pool.report_rewards(1, U512::from(10_000_000u64));
+        assert_eq!(
+            pool.try_quote_instant_withdraw(U512::MAX),
+            Err(Error::ArithmeticOverflow.into())
+        );
+
+        // Burning more than the balance reverts with the token error, not an underflow
+        env.set_caller(user);
+        assert_eq!(
+            pool.try_withdraw(cspr(1_001), false),
+            Err(TokenError::InsufficientBalance.into())
+        );
+        assert_eq!(pool.get_total_staked(), cspr(1_000) + U512::from(10_000_000u64));
+    }
+
+    // Exposes pool internals whose books cannot go wrong through the entrypoints alone
+    #[odra::module]
+    pub struct PoolHarness {
+        pool: SubModule<StakingPool>,
+    }
+
+    #[odra::module]
+    impl PoolHarness {
+        pub fn record_deposit(&mut self, account: Address, amount: U512) {
+            self.pool.record_deposit(account, amount);
+        }
+
+        pub fn queue_redemption(&mut self, receiver: Address, amount: U512) {
+            self.pool.queue_redemption(receiver, amount);
+        }
+    }
+
+    #[test]
+    fn inconsistent_books_revert_with_accounting_errors() {
+        let env = odra_test::env();
+        let mut harness = PoolHarness::deploy(&env, NoArgs);
+        let account = env.get_account(1);
+
+        // Redeeming CSPR the pool does not hold underflows `total_staked`
+        assert_eq!(
+            harness.try_queue_redemption(account, cspr(1)),
+            Err(Error::AccountingUnderflow.into())
+        );
+
+        harness.record_deposit(account, U512::MAX);
+        assert_eq!(
+            harness.try_record_deposit(account, U512::one()),
+            Err(Error::ArithmeticOverflow.into())
+        );
+    }
+
+    #[test]
+    fn previews_match_execution() {
+        let (env, mut pool) = setup();
+        let (admin, user) = (env.get_account(0), env.get_account(1));
+        pool.grant_role(Role::Reporter, admin);
+        pool.with_tokens(cspr(1_000)).add_instant_liquidity();
+        pool.with_tokens(cspr(3_000)).deposit();
+        pool.report_rewards(1, U512::from(12_345_678u64));
+
+        // Off 1:1 both directions round down
+        let deposit = cspr(7) + U512::from(3u64);
+        let stcspr = pool.preview_deposit(deposit);
+        assert_eq!(pool.convert_to_stcspr(deposit), stcspr);
+        assert!(pool.convert_to_cspr(U512::from(stcspr.as_u128())) <= deposit);
+
+        env.set_caller(user);
+        pool.with_tokens(deposit).deposit();
+        assert_eq!(pool.get_stcspr_balance(user), stcspr);
+
+        let amount = U512::from(stcspr.as_u128()) / 2;
+        let queued = pool.preview_withdraw(amount, false);
+        assert_eq!(queued, pool.convert_to_cspr(amount));
+        pool.withdraw(amount, false);
+        assert_eq!(pool.get_withdrawal_request(0).unwrap().cspr_amount, queued);
+
+        let paid = pool.preview_withdraw(amount, true);
+        assert_eq!(paid, pool.quote_instant_withdraw(amount).net_amount);
+        let balance = env.balance_of(&user);
+        pool.withdraw(amount, true);
+        assert_eq!(env.balance_of(&user), balance + paid);
+    }
+
+    #[test]
+    fn vault_interface_uses_allowances_for_third_parties() {
+        let (env, mut pool) = setup();
+        let (admin, alice, bob) = (env.get_account(0), env.get_account(1), env.get_account(2));
+        pool.grant_role(Role::Reporter, admin);
+        pool.with_tokens(cspr(2_000)).deposit();
+        pool.report_rewards(1, U512::from(20_000_000u64));
+        assert_eq!(pool.asset(), None);
+        assert_eq!(pool.total_assets(), cspr(2_000) + U512::from(20_000_000u64));
+
+        // Deposit on behalf of alice
+        env.set_caller(bob);
+        let shares = pool.with_tokens(cspr(100)).vault_deposit(alice);
+        assert_eq!(shares, pool.get_stcspr_balance(alice));
+        assert_eq!(pool.get_stcspr_balance(bob), U256::zero());
+
+        // Mint rounds the CSPR taken up and refunds the excess
+        let stcspr = U256::from(cspr(100).as_u128());
+        let balance = env.balance_of(&bob);
+        let assets = pool.with_tokens(cspr(200)).vault_mint(stcspr, bob);
+        assert!(assets > cspr(100) && assets < cspr(101));
+        assert_eq!(env.balance_of(&bob), balance - assets);
+        assert_eq!(pool.get_stcspr_balance(bob), stcspr);
+        assert_eq!(
+            pool.with_tokens(cspr(100)).try_vault_mint(stcspr, bob),
+            Err(Error::InsufficientAttachedValue.into())
+        );
+
+        // Bob redeems alice's shares only up to her allowance
+        let half = shares / 2;
+        assert_eq!(
+            pool.try_vault_redeem(half, bob, alice),
+            Err(TokenError::InsufficientAllowance.into())
+        );
+        env.set_caller(alice);
+        pool.approve(bob, half * 2);
+        env.set_caller(bob);
+        let redeemed = pool.vault_redeem(half, bob, alice);
+        assert_eq!(pool.get_withdrawal_request(0).unwrap().owner, bob);
+        assert_eq!(pool.get_withdrawal_request(0).unwrap().cspr_amount, redeemed);
+
+        // Withdrawing exact assets burns shares rounded up
+        let spent = pool.vault_withdraw(cspr(10), bob, alice);
+        assert_eq!(pool.get_withdrawal_request(1).unwrap().cspr_amount, cspr(10));
+        assert!(pool.convert_to_assets(spent) >= cspr(10));
+        assert_eq!(pool.get_stcspr_allowance(alice, bob), half - spent);
+
+        assert_eq!(pool.max_redeem(alice), pool.get_stcspr_balance(alice));
+        assert_eq!(pool.max_deposit(alice), U512::MAX);
+    }
+
+    // Sends CSPR straight to a contract's purse, bypassing its entrypoints
+    #[odra::module]
+    pub struct Donor {}
+
+    #[odra::module]
+    impl Donor {
+        #[odra(payable)]
+        pub fn donate(&mut self, recipient: Address) {
+            self.env().transfer_tokens(&recipient, &self.env().attached_value());
+        }
+    }
+
+    #[test]
+    fn inflating_the_rate_behind_one_share_does_not_steal_deposits() {
+        let (env, mut pool) = setup();
+        let (attacker, victim) = (env.get_account(1), env.get_account(2));
+
+        // The attacker is the first depositor and exits all but one share
+        env.set_caller(attacker);
+        pool.with_tokens(cspr(1)).deposit();
+        pool.withdraw(cspr(1) - U512::one(), false);
+        assert_eq!(pool.get_stcspr_balance(attacker), U256::one());
+
+        // then donates 50 CSPR to the purse and has it credited as rewards
+        let mut donor = Donor::deploy(&env, NoArgs);
+        donor.with_tokens(cspr(50)).donate(*pool.address());
+        pool.sync_rewards();
+        assert_eq!(pool.get_total_staked(), cspr(50) + U512::one());
+
+        // Without the virtual offset this deposit would mint zero stCSPR
+        env.set_caller(victim);
+        pool.with_tokens(cspr(40)).deposit();
+        let shares = pool.get_stcspr_balance(victim);
+        assert_eq!(shares, U256::from(784_313_726u64));
+        assert!(pool.convert_to_cspr(U512::from(shares.as_u128())) >= cspr(40) - U512::from(1_000u64));
+
+        // and the attacker's share captured none of it
+        assert!(pool.convert_to_cspr(U512::one()) < U512::from(1_000u64));
+    }
+
+    #[test]
+    fn upgrade_keeps_storage_and_migrate_is_admin_only() {
+        let env = odra_test::env();
+        let mut pool = StakingPool::deploy_with_cfg(
+            &env,
+            StakingPoolInitArgs {
+                unbonding_delay: UNBONDING_DELAY,
+            },
+            InstallConfig::upgradable::<StakingPool>(),
+        );
+        pool.add_validator(env.get_validator(0), 10_000, cspr(1_000_000));
+        let (admin, user) = (env.get_account(0), env.get_account(1));
+        env.set_caller(user);
+        pool.with_tokens(cspr(100)).deposit();
+
+        env.set_caller(admin);
+        let mut pool = StakingPool::try_upgrade(&env, *pool.address(), NoArgs).unwrap();
+        assert_eq!(pool.get_stcspr_balance(user), U256::from(cspr(100).as_u128()));
+        assert_eq!(pool.get_total_staked(), cspr(100));
+        assert_eq!(pool.get_exchange_rate(), U512::from(RATE_PRECISION));
+        assert_eq!(pool.get_storage_version(), STORAGE_VERSION);
+
+        // Storage is current, so there is nothing to migrate from either version
+        assert_eq!(pool.try_migrate(0), Err(Error::InvalidStorageVersion.into()));
+        assert_eq!(pool.try_migrate(STORAGE_VERSION), Err(Error::InvalidStorageVersion.into()));
+        env.set_caller(user);
+        assert_eq!(pool.try_migrate(0), Err(AccessError::MissingRole.into()));
+    }
+
+    // Holds pool storage as written before versioning, with the exchange rate scaled
+    // by 1e9
+    #[odra::module]
+    pub struct LegacyPool {
+        pool: SubModule<StakingPool>,
+    }
+
+    #[odra::module]
+    impl LegacyPool {
+        pub fn init(&mut self, exchange_rate: U512) {
+            self.pool.access.setup_role(Role::DefaultAdmin, self.env().caller());
+            self.pool.exchange_rate.set(exchange_rate);
+        }
+
+        pub fn migrate(&mut self, from_version: u32) {
+            self.pool.migrate(from_version);
+        }
+
+        pub fn get_exchange_rate(&self) -> U512 {
+            self.pool.get_exchange_rate()
+        }
+
+        pub fn get_storage_version(&self) -> u32 {
+            self.pool.get_storage_version()
+        }
+    }
+
+    #[test]
+    fn migrate_rescales_the_unversioned_exchange_rate() {
+        let env = odra_test::env();
+        let previous_rate = U512::from(1_050_000_000u64);
+        let mut pool = LegacyPool::deploy(&env, LegacyPoolInitArgs {
+            exchange_rate: previous_rate,
+        });
+        assert_eq!(pool.get_storage_version(), 0);
+        assert_eq!(pool.try_migrate(STORAGE_VERSION), Err(Error::InvalidStorageVersion.into()));
+
+        pool.migrate(0);
+        let rate = U512::from(1_050_000_000_000_000_000u64);
+        assert_eq!(pool.get_exchange_rate(), rate);
+        assert_eq!(pool.get_storage_version(), STORAGE_VERSION);
+        assert!(env.emitted_event(&pool, ExchangeRateUpdated { previous_rate, rate }));
+        assert!(env.emitted_event(&pool, StorageMigrated {
+            from_version: 0,
+            to_version: STORAGE_VERSION,
+        }));
+
+        // Migrated storage is current, so it cannot be migrated again
+        assert_eq!(pool.try_migrate(0), Err(Error::InvalidStorageVersion.into()));
+    }
 }
@@ -1,7 +1,33 @@
 use odra::prelude::*;
 use odra::casper_types::U256;
 
-#[odra::module]
+#[odra::event]
+pub struct Mint {
+    pub recipient: Address,
+    pub amount: U256,
+}
+
+#[odra::event]
+pub struct Burn {
+    pub owner: Address,
+    pub amount: U256,
+}
+
+#[odra::event]
+pub struct Transfer {
+    pub sender: Address,
+    pub recipient: Address,
+    pub amount: U256,
+}
+
+#[odra::event]
+pub struct Approve {
+    pub owner: Address,
+    pub spender: Address,
+    pub amount: U256,
+}
+
+#[odra::module(events = [Mint, Burn, Transfer, Approve])]
 pub struct StCSPRToken {
     name: Var<String>,
     symbol: Var<String>,
@@ -9,17 +35,15 @@ pub struct StCSPRToken {
     total_supply: Var<U256>,
     balances: Mapping<Address, U256>,
     allowances: Mapping<(Address, Address), U256>,
-    staking_pool: Var<Address>,
 }
 
 #[odra::module]
 impl StCSPRToken {
-    pub fn init(&mut self, staking_pool: Address) {
+    pub fn init(&mut self) {
         self.name.set(String::from("Staked CSPR"));
         self.symbol.set(String::from("stCSPR"));
         self.decimals.set(9);
         self.total_supply.set(U256::zero());
-        self.staking_pool.set(staking_pool);
     }
 
     pub fn name(&self) -> String {
@@ -42,6 +66,10 @@ impl StCSPRToken {
         self.balances.get(&account).unwrap_or(U256::zero())
     }
 
+    pub fn allowance(&self, owner: Address, spender: Address) -> U256 {
+        self.allowances.get(&(owner, spender)).unwrap_or(U256::zero())
+    }
+
     pub fn transfer(&mut self, recipient: Address, amount: U256) {
         let sender = self.env().caller();
         self._transfer(sender, recipient, amount);
@@ -50,43 +78,40 @@ impl StCSPRToken {
     pub fn approve(&mut self, spender: Address, amount: U256) {
         let owner = self.env().caller();
         self.allowances.set(&(owner, spender), amount);
+        self.env().emit_event(Approve { owner, spender, amount });
     }
 
     pub fn transfer_from(&mut self, owner: Address, recipient: Address, amount: U256) {
         let spender = self.env().caller();
-        let current_allowance = self.allowances.get(&(owner, spender)).unwrap_or(U256::zero());
+        self.spend_allowance(owner, spender, amount);
+        self._transfer(owner, recipient, amount);
+    }
 
+    pub(crate) fn spend_allowance(&mut self, owner: Address, spender: Address, amount: U256) {
+        let current_allowance = self.allowance(owner, spender);
         if current_allowance < amount {
             self.env().revert(Error::InsufficientAllowance);
         }
 
         self.allowances.set(&(owner, spender), current_allowance - amount);
-        self._transfer(owner, recipient, amount);
     }
 
-    pub fn mint(&mut self, recipient: Address, amount: U256) {
-        let caller = self.env().caller();
-        let staking_pool = self.staking_pool.get_or_revert_with(Error::Unauthorized);
-
-        if caller != staking_pool {
-            self.env().revert(Error::Unauthorized);
-        }
+    // Mint and burn run inside the staking pool contract (the token is its submodule),
+    // so `caller()` is the end user here - access is restricted at the crate level instead.
+    pub(crate) fn mint(&mut self, recipient: Address, amount: U256) {
+        let total = self.total_supply();
+        let Some(new_total) = total.checked_add(amount) else {
+            self.env().revert(Error::ArithmeticOverflow);
+        };
+        self.total_supply.set(new_total);
 
+        // Balances never exceed the total supply
         let balance = self.balance_of(recipient);
         self.balances.set(&recipient, balance + amount);
-
-        let total = self.total_supply();
-        self.total_supply.set(total + amount);
+        self.env().emit_event(Mint { recipient, amount });
     }
 
-    pub fn burn(&mut self, account: Address, amount: U256) {
-        let caller = self.env().caller();
-        let staking_pool = self.staking_pool.get_or_revert_with(Error::Unauthorized);
-
-        if caller != staking_pool {
-            self.env().revert(Error::Unauthorized);
-        }
-
+    pub(crate) fn burn(&mut self, account: Address, amount: U256) {
         let balance = self.balance_of(account);
         if balance < amount {
             self.env().revert(Error::InsufficientBalance);
@@ -95,7 +120,11 @@ impl StCSPRToken {
         self.balances.set(&account, balance - amount);
 
         let total = self.total_supply();
-        self.total_supply.set(total - amount);
+        let Some(new_total) = total.checked_sub(amount) else {
+            self.env().revert(Error::AccountingUnderflow);
+        };
+        self.total_supply.set(new_total);
+        self.env().emit_event(Burn { owner: account, amount });
     }
 
     fn _transfer(&mut self, sender: Address, recipient: Address, amount: U256) {
@@ -108,12 +137,64 @@ impl StCSPRToken {
 
         self.balances.set(&sender, sender_balance - amount);
         self.balances.set(&recipient, recipient_balance + amount);
+        self.env().emit_event(Transfer { sender, recipient, amount });
     }
 }
 
 #[odra::odra_error]
 pub enum Error {
     InsufficientBalance = 1,
-    Unauthorized = 2,
     InsufficientAllowance = 3,
+    ArithmeticOverflow = 4,
+    AccountingUnderflow = 5,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, StCSPRToken};
+    use odra::casper_types::U256;
+    use odra::host::{Deployer, NoArgs};
+    use odra::prelude::*;
+
+    // Exposes the pool-only mint and burn, and a way to break the supply invariant
+    #[odra::module]
+    pub struct TokenHarness {
+        token: SubModule<StCSPRToken>,
+    }
+
+    #[odra::module]
+    impl TokenHarness {
+        pub fn mint(&mut self, recipient: Address, amount: U256) {
+            self.token.mint(recipient, amount);
+        }
+
+        pub fn burn(&mut self, owner: Address, amount: U256) {
+            self.token.burn(owner, amount);
+        }
+
+        pub fn set_balance(&mut self, account: Address, amount: U256) {
+            self.token.balances.set(&account, amount);
+        }
+
+        pub fn total_supply(&self) -> U256 {
+            self.token.total_supply()
+        }
+    }
+
+    #[test]
+    fn supply_overflow_and_underflow_revert() {
+        let env = odra_test::env();
+        let mut token = TokenHarness::deploy(&env, NoArgs);
+        let (alice, bob) = (env.get_account(1), env.get_account(2));
+
+        token.mint(alice, U256::MAX);
+        assert_eq!(token.try_mint(bob, U256::one()), Err(Error::ArithmeticOverflow.into()));
+        assert_eq!(token.total_supply(), U256::MAX);
+
+        // A balance the supply does not account for cannot be burned
+        token.burn(alice, U256::MAX);
+        token.set_balance(bob, U256::from(10));
+        assert_eq!(token.try_burn(bob, U256::from(10)), Err(Error::AccountingUnderflow.into()));
+        assert_eq!(token.total_supply(), U256::zero());
+    }
 }
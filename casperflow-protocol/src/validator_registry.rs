@@ -0,0 +1,115 @@
+use odra::prelude::*;
+use odra::casper_types::{PublicKey, U512};
+
+const MAX_TOTAL_WEIGHT_BP: u32 = 10_000; // 100% in basis points
+
+#[odra::odra_type]
+pub struct ValidatorInfo {
+    pub public_key: PublicKey,
+    pub enabled: bool,
+    pub weight_bp: u32,  // target share of the pool's stake
+    pub cap: U512,       // maximum CSPR the pool delegates to this validator
+}
+
+/// Allowlist of validators the staking pool may delegate to.
+#[odra::module]
+pub struct ValidatorRegistry {
+    validators: Var<Vec<PublicKey>>,
+    info: Mapping<PublicKey, ValidatorInfo>,
+}
+
+#[odra::module]
+impl ValidatorRegistry {
+    pub fn get(&self, public_key: PublicKey) -> Option<ValidatorInfo> {
+        if self.is_registered(&public_key) {
+            self.info.get(&public_key)
+        } else {
+            None
+        }
+    }
+
+    pub fn all(&self) -> Vec<ValidatorInfo> {
+        self.validators
+            .get_or_default()
+            .iter()
+            .filter_map(|public_key| self.info.get(public_key))
+            .collect()
+    }
+
+    pub fn enabled(&self) -> Vec<ValidatorInfo> {
+        self.all().into_iter().filter(|info| info.enabled).collect()
+    }
+
+    pub fn total_weight(&self) -> u32 {
+        self.all().iter().map(|info| info.weight_bp).sum()
+    }
+
+    pub(crate) fn add(&mut self, public_key: PublicKey, weight_bp: u32, cap: U512) {
+        if self.is_registered(&public_key) {
+            self.env().revert(Error::ValidatorAlreadyRegistered);
+        }
+        self.check_total_weight(self.total_weight() + weight_bp);
+
+        let mut validators = self.validators.get_or_default();
+        validators.push(public_key.clone());
+        self.validators.set(validators);
+
+        self.info.set(&public_key, ValidatorInfo {
+            public_key: public_key.clone(),
+            enabled: true,
+            weight_bp,
+            cap,
+        });
+    }
+
+    pub(crate) fn set_enabled(&mut self, public_key: PublicKey, enabled: bool) {
+        let mut info = self.get_or_revert(&public_key);
+        info.enabled = enabled;
+        self.info.set(&public_key, info);
+    }
+
+    pub(crate) fn set_weight(&mut self, public_key: PublicKey, weight_bp: u32) {
+        let mut info = self.get_or_revert(&public_key);
+        self.check_total_weight(self.total_weight() - info.weight_bp + weight_bp);
+        info.weight_bp = weight_bp;
+        self.info.set(&public_key, info);
+    }
+
+    pub(crate) fn set_cap(&mut self, public_key: PublicKey, cap: U512) {
+        let mut info = self.get_or_revert(&public_key);
+        info.cap = cap;
+        self.info.set(&public_key, info);
+    }
+
+    pub(crate) fn remove(&mut self, public_key: PublicKey) {
+        self.get_or_revert(&public_key);
+
+        let mut validators = self.validators.get_or_default();
+        validators.retain(|validator| *validator != public_key);
+        self.validators.set(validators);
+    }
+
+    fn is_registered(&self, public_key: &PublicKey) -> bool {
+        self.validators.get_or_default().contains(public_key)
+    }
+
+    fn get_or_revert(&self, public_key: &PublicKey) -> ValidatorInfo {
+        match self.get(public_key.clone()) {
+            Some(info) => info,
+            None => self.env().revert(Error::ValidatorNotFound),
+        }
+    }
+
+    fn check_total_weight(&self, total_weight: u32) {
+        if total_weight > MAX_TOTAL_WEIGHT_BP {
+            self.env().revert(Error::WeightsExceedTotal);
+        }
+    }
+}
+
+#[odra::odra_error]
+pub enum Error {
+    ValidatorNotFound = 300,
+    ValidatorAlreadyRegistered = 301,
+    WeightsExceedTotal = 302,
+}
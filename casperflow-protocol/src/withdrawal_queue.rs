@@ -0,0 +1,165 @@
+use odra::prelude::*;
+use odra::casper_types::U512;
+
+#[odra::odra_type]
+pub enum WithdrawalStatus {
+    Pending,
+    Claimed,
+}
+
+#[odra::odra_type]
+pub struct WithdrawalRequest {
+    pub id: u64,
+    pub owner: Address,
+    pub cspr_amount: U512,
+    pub created_at: u64,
+    pub claimable_at: u64,
+    pub status: WithdrawalStatus,
+}
+
+/// Standard (non-instant) withdrawals waiting out the unbonding delay.
+///
+/// Receivables are requests settled oldest first rather than claimed by id; they are
+/// kept in a FIFO between `receivables_head` and `receivables_tail`, so settling one
+/// costs the same however long the backlog grows.
+#[odra::module]
+pub struct WithdrawalQueue {
+    requests: Mapping<u64, WithdrawalRequest>,
+    owner_requests: Mapping<Address, Vec<u64>>,
+    next_id: Var<u64>,
+    unbonding_delay: Var<u64>,  // in milliseconds
+    total_pending: Var<U512>,
+    receivables: Mapping<u64, u64>,  // request ids by queue position
+    receivables_head: Var<u64>,
+    receivables_tail: Var<u64>,
+}
+
+#[odra::module]
+impl WithdrawalQueue {
+    pub fn init(&mut self, unbonding_delay: u64) {
+        self.next_id.set(0);
+        self.unbonding_delay.set(unbonding_delay);
+        self.total_pending.set(U512::zero());
+    }
+
+    pub fn unbonding_delay(&self) -> u64 {
+        self.unbonding_delay.get_or_default()
+    }
+
+    pub fn total_pending(&self) -> U512 {
+        self.total_pending.get_or_default()
+    }
+
+    pub fn get(&self, request_id: u64) -> Option<WithdrawalRequest> {
+        self.requests.get(&request_id)
+    }
+
+    pub fn pending_of(&self, owner: Address) -> Vec<WithdrawalRequest> {
+        self.owner_requests
+            .get(&owner)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|id| self.requests.get(id))
+            .collect()
+    }
+
+    /// At most `count` receivables not settled yet, oldest first.
+    pub fn pending_receivables(&self, count: u32) -> Vec<WithdrawalRequest> {
+        let head = self.receivables_head.get_or_default();
+        let tail = self.receivables_tail.get_or_default();
+        (head..tail.min(head.saturating_add(count as u64)))
+            .filter_map(|position| self.receivables.get(&position))
+            .filter_map(|id| self.requests.get(&id))
+            .collect()
+    }
+
+    pub(crate) fn enqueue(&mut self, owner: Address, cspr_amount: U512) -> u64 {
+        let id = self.create(owner, cspr_amount);
+        let mut ids = self.owner_requests.get(&owner).unwrap_or_default();
+        ids.push(id);
+        self.owner_requests.set(&owner, ids);
+        id
+    }
+
+    /// Queues a receivable, settled through `settle_next_receivable` once it matures.
+    pub(crate) fn enqueue_receivable(&mut self, owner: Address, cspr_amount: U512) -> u64 {
+        let id = self.create(owner, cspr_amount);
+        let tail = self.receivables_tail.get_or_default();
+        self.receivables.set(&tail, id);
+        self.receivables_tail.set(tail + 1);
+        id
+    }
+
+    /// Settles the oldest receivable and returns its CSPR, or `None` when there is
+    /// none or it has not matured. Receivables mature in the order they were queued.
+    pub(crate) fn settle_next_receivable(&mut self) -> Option<U512> {
+        let head = self.receivables_head.get_or_default();
+        if head == self.receivables_tail.get_or_default() {
+            return None;
+        }
+        let request_id = self.receivables.get(&head)?;
+        let request = self.requests.get(&request_id)?;
+        if self.env().get_block_time() < request.claimable_at {
+            return None;
+        }
+        self.receivables_head.set(head + 1);
+        Some(self.mark_claimed(request))
+    }
+
+    fn create(&mut self, owner: Address, cspr_amount: U512) -> u64 {
+        let id = self.next_id.get_or_default();
+        let now = self.env().get_block_time();
+
+        self.requests.set(&id, WithdrawalRequest {
+            id,
+            owner,
+            cspr_amount,
+            created_at: now,
+            claimable_at: now + self.unbonding_delay(),
+            status: WithdrawalStatus::Pending,
+        });
+
+        self.next_id.set(id + 1);
+        self.total_pending.set(self.total_pending() + cspr_amount);
+        id
+    }
+
+    /// Marks a matured request as claimed and returns the CSPR owed to `owner`.
+    pub(crate) fn claim(&mut self, owner: Address, request_id: u64) -> U512 {
+        let Some(request) = self.requests.get(&request_id) else {
+            self.env().revert(Error::WithdrawalNotFound)
+        };
+
+        if request.owner != owner {
+            self.env().revert(Error::NotRequestOwner);
+        }
+        if request.status == WithdrawalStatus::Claimed {
+            self.env().revert(Error::WithdrawalAlreadyClaimed);
+        }
+        if self.env().get_block_time() < request.claimable_at {
+            self.env().revert(Error::WithdrawalNotReady);
+        }
+
+        let mut ids = self.owner_requests.get(&owner).unwrap_or_default();
+        ids.retain(|id| *id != request_id);
+        self.owner_requests.set(&owner, ids);
+
+        self.mark_claimed(request)
+    }
+
+    fn mark_claimed(&mut self, mut request: WithdrawalRequest) -> U512 {
+        request.status = WithdrawalStatus::Claimed;
+        let amount = request.cspr_amount;
+        self.requests.set(&request.id, request);
+        self.total_pending.set(self.total_pending() - amount);
+        amount
+    }
+}
+
+#[odra::odra_error]
+pub enum Error {
+    WithdrawalNotFound = 200,
+    NotRequestOwner = 201,
+    WithdrawalNotReady = 202,
+    WithdrawalAlreadyClaimed = 203,
+}